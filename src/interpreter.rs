@@ -1,4 +1,17 @@
-pub fn execute_ir(code: &str) {
+// settings from the command line.
+pub struct RunOptions {
+    // makes '%rand' reproducible, without one the current time is used.
+    pub seed: Option<u64>,
+    // input that isn't a number is a runtime error instead of asking again.
+    pub strict_input: bool,
+    // the arguments after the source file, passed to 'main' as argc and read with '%arg'.
+    pub args: Vec<i32>,
+    // reports arrays created with '%new' that are still alive once the program ends.
+    pub debug_heap: bool,
+}
+
+// returns the exit status of the program, 1 if it could not run or stopped with an error.
+pub fn execute_ir(code: &str, options: &RunOptions) -> i32 {
     let tokens = lex_ir(code);
     let bytecode = match parse_ir(&tokens, &mut 0) {
     Ok(bytecode) => {
@@ -17,16 +30,137 @@ pub fn execute_ir(code: &str) {
         }
         println!("------------------");
         println!("{e}");
-        return;
+        return 1;
     }
 
     };
 
-    let stdin = io::stdin();
-    run_program(&stdin, &bytecode);
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0)
+    });
+    let mut runtime = Runtime {
+        input: Input::new(options.strict_input),
+        random: Random::new(seed),
+        args: options.args.clone(),
+        heap: if options.debug_heap { Some(vec![]) } else { None },
+    };
+    let status = run_program(&mut runtime, &bytecode);
+    // the process exits right after, output without a newline would be lost otherwise.
+    let _ = io::stdout().flush();
+    status
+}
+
+// state shared by every function call of a running program.
+struct Runtime {
+    input: Input,
+    random: Random,
+    args: Vec<i32>,
+    // every array created with '%new', only kept with --debug-heap.
+    heap: Option<Vec<Allocation>>,
+}
+
+// arrays are freed as soon as the last variable or array element referring to them is gone,
+// so anything still alive after 'main' returned has leaked.
+struct Allocation {
+    array: Weak<RefCell<Vec<Value>>>,
+    function: String,
+    len: usize,
 }
 
 use std::io;
+use std::io::{BufRead, Write};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// standard input, shared by '%input', '%getc' and '%eof'.
+// it is read a line at a time, so a '%getc' followed by an '%input' reads the rest of the line.
+struct Input {
+    stdin: io::Stdin,
+    buffer: VecDeque<u8>,
+    end: bool,
+    strict: bool,
+}
+
+impl Input {
+    fn new(strict: bool) -> Input {
+        Input {
+            stdin: io::stdin(),
+            buffer: VecDeque::new(),
+            end: false,
+            strict,
+        }
+    }
+
+    // reads the next line once the buffered one has been used up.
+    fn fill(&mut self) -> Result<(), IRError> {
+        if !self.buffer.is_empty() || self.end {
+            return Ok(());
+        }
+        let mut line = vec![];
+        match self.stdin.lock().read_until(b'\n', &mut line) {
+        Ok(0) => self.end = true,
+        Ok(_) => self.buffer.extend(line),
+        Err(e) => return error(MAX_LINE, format!("Error. Failed to read from standard input correctly. {e}")),
+        }
+        Ok(())
+    }
+
+    // the next byte, or -1 at the end of the input.
+    fn getc(&mut self) -> Result<i32, IRError> {
+        self.fill()?;
+        Ok(self.buffer.pop_front().map(|byte| byte as i32).unwrap_or(-1))
+    }
+
+    fn eof(&mut self) -> Result<bool, IRError> {
+        self.fill()?;
+        Ok(self.buffer.is_empty())
+    }
+
+    // the rest of the current line, None at the end of the input.
+    fn read_line(&mut self) -> Result<Option<String>, IRError> {
+        self.fill()?;
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let len = match self.buffer.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => newline + 1,
+            None => self.buffer.len(),
+        };
+        let line: Vec<u8> = self.buffer.drain(..len).collect();
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+// xorshift64* pseudo-random number generator behind '%rand' and '%srand'.
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        let mut random = Random { state: 0 };
+        random.seed(seed);
+        random
+    }
+
+    fn seed(&mut self, seed: u64) {
+        // the generator gets stuck on a state of 0.
+        self.state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        if self.state == 0 {
+            self.state = 0x9E37_79B9_7F4A_7C15;
+        }
+    }
+
+    // a number between 0 and i32::MAX.
+    fn next(&mut self) -> i32 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 33) as i32
+    }
+}
 
 fn lex_ir(mut code: &str) -> Vec<IRTok> {
     let mut tokens: Vec<IRTok> = vec![];
@@ -67,13 +201,22 @@ fn parse_ir(tokens: &Vec<IRTok>, idx: &mut usize) -> Result< Vec<FunctionBytecod
     // todo: this is not the correct line numbers. but I dunno how to get better line numbers...
     for func_id in 0..vector.len() {
         for instr_id in 0..vector[func_id].body.len() {
+            if let Bytecode::FuncRef(dest, name) = &vector[func_id].body[instr_id] {
+                match &tokens[*name] {
+                IRTok::Var(func_name) => match find_func_id(&vector, func_name) {
+                    Some(call_value) => vector[func_id].body[instr_id] = Bytecode::FuncRef(*dest, call_value),
+                    None => return error(MAX_LINE, format!("Error. Undeclared function '{}'", func_name)),
+                },
+                _ => return error(MAX_LINE, String::from("Internal Interpreter Error.")),
+                }
+            }
             if let Bytecode::Call(r, call, params) = &vector[func_id].body[instr_id] {
                 if let IRTok::Var(func_name) = &tokens[*call] {
                     if let Some(call_value) = find_func_id(&vector, func_name) {
                          if params.len() != vector[call_value].parameters {
                              return error(MAX_LINE, format!("Error. Invalid parameter passing to '{func_name}'. Expected {} number of parameters. Got {} number of parameters.", vector[call_value].parameters, params.len()));
                          }
-                         vector[func_id].body[instr_id] = Bytecode::Call(*r, call_value, params.to_vec());
+                         vector[func_id].body[instr_id] = Bytecode::Call(r.to_vec(), call_value, params.to_vec());
                     } else {
                          return error(MAX_LINE, format!("Error. Undeclared function '{}'", func_name));
                     }
@@ -111,8 +254,20 @@ impl fmt::Display for IRError {
     }
 }
 
-fn error<T>(line: usize, message: String) -> Result<T, IRError> {
-    Err(IRError {line: line, message: message})
+fn error<T, E: From<IRError>>(line: usize, message: String) -> Result<T, E> {
+    Err(E::from(IRError {line, message}))
+}
+
+// stops every function on the call stack, either because of an error or a call to '%exit'.
+enum Trap {
+    Error(IRError),
+    Exit(i32),
+}
+
+impl From<IRError> for Trap {
+    fn from(e: IRError) -> Self {
+        Trap::Error(e)
+    }
 }
 
 use std::fmt;
@@ -162,16 +317,26 @@ fn parse_func_ir(serialized_line: &mut usize, tokens: &Vec<IRTok>, idx: &mut usi
         loop {
             match next_result(*serialized_line, tokens, idx)? {
             IRTok::RParen => break,
-            IRTok::Int => {
+            // arrays are passed by reference as '%int[] array'.
+            IRTok::Int | IRTok::Float | IRTok::IntArray | IRTok::FloatArray | IRTok::IntHandleArray | IRTok::FloatHandleArray => {
+                let declaration = &tokens[*idx - 1];
                 match next_result(*serialized_line, tokens, idx)? {
                 IRTok::Var(param) => {
-                    if let Some(_) = function_bytecode.variables.get(param) {
+                    if function_bytecode.variables.contains_key(param) {
                         let f = format!("identifier {param} already defined");
                         return error(*serialized_line, f);
                     } else {
                         function_bytecode.parameters += 1;
                         let id = get_id(&mut function_bytecode);
-                        function_bytecode.variables.insert(param.clone(), VariableType::IntVar(id));
+                        let variable = match declaration {
+                        IRTok::Float => VariableType::Float(id),
+                        IRTok::IntArray => VariableType::Array(id, 0),
+                        IRTok::FloatArray => VariableType::FloatArray(id, 0),
+                        IRTok::IntHandleArray => VariableType::HandleArray(id, 0, ValueType::Int),
+                        IRTok::FloatHandleArray => VariableType::HandleArray(id, 0, ValueType::Float),
+                        _ => VariableType::Int(id),
+                        };
+                        function_bytecode.variables.insert(param.clone(), variable);
                     }
                     if matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::Comma) {
                         *idx += 1;
@@ -257,6 +422,8 @@ fn parse_func_ir(serialized_line: &mut usize, tokens: &Vec<IRTok>, idx: &mut usi
 }
 
 use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
 
 struct FunctionBytecode {
     name: String,
@@ -272,14 +439,23 @@ fn get_id(function: &mut FunctionBytecode) -> i32 {
     id
 }
 
-fn read_integer_value(variables: &HashMap<i32, i32>, op: &Op) -> i32 {
+fn read_value(variables: &HashMap<i32, Value>, op: &Op) -> Value {
     match op {
-    Op::Num(num) => *num,
-    Op::Var(id) => *variables.get(&id).unwrap(),
+    Op::Num(num) => Value::Int(*num),
+    Op::Float(num) => Value::Float(*num),
+    Op::Var(id) => variables.get(id).unwrap().clone(),
     }
 }
 
-fn run_program(stdin: &io::Stdin, calls: &Vec<FunctionBytecode>) {
+fn read_integer_value(variables: &HashMap<i32, Value>, op: &Op) -> i32 {
+    read_value(variables, op).as_int()
+}
+
+fn read_float_value(variables: &HashMap<i32, Value>, op: &Op) -> f64 {
+    read_value(variables, op).as_float()
+}
+
+fn run_program(runtime: &mut Runtime, calls: &Vec<FunctionBytecode>) -> i32 {
     let entry_point = {
         let mut result = None;
         for func in calls {
@@ -291,31 +467,75 @@ fn run_program(stdin: &io::Stdin, calls: &Vec<FunctionBytecode>) {
         Some(r) => r,
         None => {
             println!("Runtime Error. No main function declared.");
-            return;
+            return 1;
         }
         }
     };
-    match run_bytecode(stdin, entry_point, calls, &vec![]) {
-    Ok(n) => println!("Run successful. Exit code {}", n),
-    Err(e) => println!("{}", e),
+    // main(int argc) receives the number of program arguments.
+    let mut parameters = vec![];
+    if entry_point.parameters == 1 {
+        parameters.push(Argument::Value(Value::Int(runtime.args.len() as i32)));
+    }
+    let result = run_bytecode(runtime, entry_point, calls, &parameters).map(|values| values[0].clone());
+    // every frame is gone by now.
+    if let Some(heap) = &runtime.heap {
+        report_heap(heap);
+    }
+    match result {
+    Ok(Value::Int(n)) | Err(Trap::Exit(n)) => {
+        println!("Run successful. Exit code {}", n);
+        n
+    }
+    Ok(n) => {
+        println!("Run successful. Exit code {}", n);
+        0
+    }
+    Err(Trap::Error(e)) => {
+        println!("{}", e);
+        1
+    }
+    }
+}
+
+fn report_heap(heap: &[Allocation]) {
+    let leaks: Vec<&Allocation> = heap.iter().filter(|allocation| allocation.array.strong_count() > 0).collect();
+    println!("Heap: {} arrays allocated, {} freed, {} leaked.", heap.len(), heap.len() - leaks.len(), leaks.len());
+    for leak in leaks {
+        println!("Leaked an array of {} elements allocated in function '{}'.", leak.len, leak.function);
     }
 }
 
-fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<FunctionBytecode>, parameters: &Vec<i32>) -> Result<i32, IRError>  {
-    let mut variables: HashMap<i32, i32> = HashMap::new();
-    let mut arrays: HashMap<i32, Vec<i32>> = HashMap::new();
+fn run_bytecode(runtime: &mut Runtime, function: &FunctionBytecode, calls: &Vec<FunctionBytecode>, parameters: &[Argument]) -> Result<Vec<Value>, Trap>  {
+    let mut variables: HashMap<i32, Value> = HashMap::new();
+    let mut arrays: HashMap<i32, Array> = HashMap::new();
 
     // setup local variables
     for (_, vartype) in &function.variables {
          match vartype {
-         VariableType::IntVar(id) => {
-             variables.insert(*id, 0);
+         VariableType::Int(id) => {
+             variables.insert(*id, Value::Int(0));
          }
 
-         VariableType::ArrayVar(id, len) => {
+         VariableType::Float(id) => {
+             variables.insert(*id, Value::Float(0.0));
+         }
+
+         // arrays sized at runtime have a length of 0 until their declaration runs.
+         VariableType::Array(id, len) => {
              // create an array of zeroes.
-             let arr = vec![0i32; *len as usize];
-             arrays.insert(*id, arr);
+             let arr = vec![Value::Int(0); *len as usize];
+             arrays.insert(*id, Rc::new(RefCell::new(arr)));
+         }
+
+         VariableType::FloatArray(id, len) => {
+             let arr = vec![Value::Float(0.0); *len as usize];
+             arrays.insert(*id, Rc::new(RefCell::new(arr)));
+         }
+
+         // the elements don't refer to an array until one is stored in them.
+         VariableType::HandleArray(id, len, _) => {
+             let arr = vec![Value::Handle(None); *len as usize];
+             arrays.insert(*id, Rc::new(RefCell::new(arr)));
          }
 
          }
@@ -326,9 +546,33 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
     }
 
     // hopefully this covers everything needed for parameter passing...
-    for (i, value) in parameters.iter().enumerate() {
-        let variable = variables.get_mut(&(i as i32)).unwrap();
-        *variable = *value;
+    for (i, argument) in parameters.iter().enumerate() {
+        let id = i as i32;
+        match argument {
+        Argument::Value(value) => {
+            let variable = match variables.get_mut(&id) {
+                Some(variable) => variable,
+                None => {
+                    let e = format!("Runtime Error. Parameter {} of function '{}' is an array, but {} was passed.", i + 1, function.name, value.type_name());
+                    return error(MAX_LINE, e);
+                }
+            };
+            if !variable.same_type(value) {
+                let e = format!("Runtime Error. Parameter {} of function '{}' is {}, but {} was passed.", i + 1, function.name, variable.type_name(), value.type_name());
+                return error(MAX_LINE, e);
+            }
+            *variable = value.clone();
+        }
+
+        // the callee shares the caller's array.
+        Argument::Array(array) => {
+            if !arrays.contains_key(&id) {
+                let e = format!("Runtime Error. Parameter {} of function '{}' is not an array, but an array was passed.", i + 1, function.name);
+                return error(MAX_LINE, e);
+            }
+            arrays.insert(id, Rc::clone(array));
+        }
+        }
     }
 
     // a lot of unwraps, but we already checked everything
@@ -344,87 +588,168 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
 
         Bytecode::Int(id) => {
             let value = variables.get_mut(id).unwrap();
-            *value = 0;
+            *value = Value::Int(0);
             instr_pointer += 1;
         }
 
-        Bytecode::IntArray(id, len) => {
-            let array = arrays.get_mut(id).unwrap();
-            for i in 0..*len {
-                 let idx = i as usize;
-                 array[idx] ^= array[idx];
+        Bytecode::Float(id) => {
+            let value = variables.get_mut(id).unwrap();
+            *value = Value::Float(0.0);
+            instr_pointer += 1;
+        }
+
+        Bytecode::IntArray(id, dims) => {
+            let len = array_length(&variables, dims)?;
+            arrays.insert(*id, Rc::new(RefCell::new(vec![Value::Int(0); len])));
+            instr_pointer += 1;
+        }
+
+        Bytecode::FloatArray(id, dims) => {
+            let len = array_length(&variables, dims)?;
+            arrays.insert(*id, Rc::new(RefCell::new(vec![Value::Float(0.0); len])));
+            instr_pointer += 1;
+        }
+
+        Bytecode::HandleArray(id, dims) => {
+            let len = array_length(&variables, dims)?;
+            arrays.insert(*id, Rc::new(RefCell::new(vec![Value::Handle(None); len])));
+            instr_pointer += 1;
+        }
+
+        Bytecode::ArrayRef(id) => {
+            arrays.remove(id);
+            instr_pointer += 1;
+        }
+
+        Bytecode::New(dest, size, element_type) => {
+            let len = array_length(&variables, std::slice::from_ref(size))?;
+            let zero = match element_type {
+            ValueType::Int => Value::Int(0),
+            ValueType::Float => Value::Float(0.0),
+            };
+            let array = Rc::new(RefCell::new(vec![zero; len]));
+            if let Some(heap) = &mut runtime.heap {
+                heap.push(Allocation { array: Rc::downgrade(&array), function: function.name.clone(), len });
+            }
+            arrays.insert(*dest, array);
+            instr_pointer += 1;
+        }
+
+        Bytecode::Bind(dest, src) => {
+            let handle = arrays.get(src).cloned();
+            bind(&mut arrays, *dest, handle);
+            instr_pointer += 1;
+        }
+
+        Bytecode::LoadHandle(dest, src) => {
+            match read_memory(&variables, &arrays, src)? {
+            Value::Handle(handle) => bind(&mut arrays, *dest, handle),
+            _ => unreachable!("arrays of arrays only hold handles"),
             }
             instr_pointer += 1;
         }
 
+        Bytecode::StoreHandle(dest, src) => {
+            let handle = Value::Handle(arrays.get(src).cloned());
+            write_memory(&mut variables, &arrays, dest, handle)?;
+            instr_pointer += 1;
+        }
+
+        Bytecode::Len(dest, id) => {
+            let len = get_array(&arrays, id)?.borrow().len();
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(len as i32);
+            instr_pointer += 1;
+        }
+
         Bytecode::Out(value) => {
-            let num = read_integer_value(&variables, value);
-            println!("{}", num);
+            let value = read_value(&variables, value);
+            println!("{}", value);
             instr_pointer += 1;
         }
 
-        Bytecode::In(id) => {
-            let mut buf = String::with_capacity(64);
-            loop {
-                match stdin.read_line(&mut buf) {
-                Ok(_) => {
-                     let token = buf.trim_end();
-                     match token.parse::<i32>() {
-                     Ok(num) => {
-                          let dest = variables.get_mut(id).unwrap();
-                          *dest = num;
-                          break;
-                     }
+        Bytecode::OutStr(text) => {
+            print!("{}", text);
+            instr_pointer += 1;
+        }
 
-                     Err(_) => {
-                         println!("User Input Error. '{}' is not a valid number.", token);
-                         buf.clear();
+        Bytecode::OutVal(value) => {
+            let value = read_value(&variables, value);
+            print!("{}", value);
+            instr_pointer += 1;
+        }
+
+        Bytecode::In(dest) => {
+            // the current value decides whether an int or a float is read.
+            let current = read_memory(&variables, &arrays, &dest.as_read())?;
+            let value = loop {
+                match runtime.input.read_line()? {
+                // the end of the input reads as 0, programs can tell the difference with '%eof'.
+                None => {
+                     break current.parse_same_type("0").unwrap();
+                }
+
+                Some(line) => {
+                     let token = line.trim();
+                     match current.parse_same_type(token) {
+                     Some(value) => {
+                          break value;
                      }
 
+                     None => {
+                         if runtime.input.strict {
+                             let e = format!("Runtime Error. User Input '{}' is not {}.", token, current.type_name());
+                             return error(MAX_LINE, e);
+                         }
+                         println!("User Input Error. '{}' is not {}.", token, current.type_name());
                      }
-                }
 
-                Err(e) => {
-                     println!("Error. Failed to read from standard input correctly.");
-                     println!("{e}");
-                     println!("Please try again.");
+                     }
                 }
 
                 }
-            }
+            };
+            write_memory(&mut variables, &arrays, dest, value)?;
             instr_pointer += 1;
         }
 
-        Bytecode::Mov(MemWrite::IntVar(dest), src) => {
-            let num = read_memory(&variables, &arrays, src)?;
+        Bytecode::Getc(dest) => {
+            let byte = runtime.input.getc()?;
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num;
+            *dest = Value::Int(byte);
             instr_pointer += 1;
         }
 
-        Bytecode::Mov(MemWrite::ArrayWrite(dest, index), src) => {
-            let num = read_memory(&variables, &arrays, src)?;
-            let dest = arrays.get_mut(dest).unwrap();
-            let index = read_integer_value(&variables, index);
-            if index < 0 {
-                let e = format!("Runtime Error: Array out of bounds. Value {}. Array Length {}", index, dest.len());
+        Bytecode::Putc(src) => {
+            let byte = read_integer_value(&variables, src);
+            if !(0..=255).contains(&byte) {
+                let e = format!("Runtime Error. putc expects a byte between 0 and 255, but got {}.", byte);
                 return error(MAX_LINE, e);
             }
-            let i = index as usize;
-            if i < dest.len() {
-                dest[i] = num;
-                instr_pointer += 1;
-            } else {
-                let e = format!("Runtime Error: Array out of bounds. Value {}. Array Length {}", i, dest.len());
-                return error(MAX_LINE, e);
+            if let Err(e) = io::stdout().write_all(&[byte as u8]) {
+                return error(MAX_LINE, format!("Error. Failed to write to standard output. {e}"));
             }
+            instr_pointer += 1;
+        }
+
+        Bytecode::Eof(dest) => {
+            let end = runtime.input.eof()?;
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(end as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::Mov(dest, src) => {
+            let value = read_memory(&variables, &arrays, src)?;
+            write_memory(&mut variables, &arrays, dest, value)?;
+            instr_pointer += 1;
         }
 
         Bytecode::Add(dest, src1, src2) => {
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num1 + num2;
+            *dest = Value::Int(num1 + num2);
             instr_pointer += 1;
         }
 
@@ -432,7 +757,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num1 - num2;
+            *dest = Value::Int(num1 - num2);
             instr_pointer += 1;
         }
 
@@ -440,7 +765,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num1 * num2;
+            *dest = Value::Int(num1 * num2);
             instr_pointer += 1;
         }
 
@@ -452,7 +777,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
                 return error(MAX_LINE, e);
             }
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num1 / num2;
+            *dest = Value::Int(num1 / num2);
             instr_pointer += 1;
         }
 
@@ -464,7 +789,195 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
                 return error(MAX_LINE, e);
             }
             let dest = variables.get_mut(dest).unwrap();
-            *dest = num1 % num2;
+            *dest = Value::Int(num1 % num2);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FAdd(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1 + num2);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FSub(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1 - num2);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FMult(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1 * num2);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FDiv(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            if num2 == 0.0 {
+                let e = String::from("Error. Attempt to divide by zero.");
+                return error(MAX_LINE, e);
+            }
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1 / num2);
+            instr_pointer += 1;
+        }
+
+        Bytecode::Abs(dest, src) => {
+            let num = read_integer_value(&variables, src);
+            let result = match num.checked_abs() {
+                Some(result) => result,
+                None => {
+                    let e = format!("Runtime Error. The absolute value of {} does not fit in an int.", num);
+                    return error(MAX_LINE, e);
+                }
+            };
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(result);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FAbs(dest, src) => {
+            let num = read_float_value(&variables, src);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num.abs());
+            instr_pointer += 1;
+        }
+
+        Bytecode::Min(dest, src1, src2) => {
+            let num1 = read_integer_value(&variables, src1);
+            let num2 = read_integer_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(num1.min(num2));
+            instr_pointer += 1;
+        }
+
+        Bytecode::FMin(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1.min(num2));
+            instr_pointer += 1;
+        }
+
+        Bytecode::Max(dest, src1, src2) => {
+            let num1 = read_integer_value(&variables, src1);
+            let num2 = read_integer_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(num1.max(num2));
+            instr_pointer += 1;
+        }
+
+        Bytecode::FMax(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1.max(num2));
+            instr_pointer += 1;
+        }
+
+        Bytecode::Pow(dest, src1, src2) => {
+            let num1 = read_integer_value(&variables, src1);
+            let num2 = read_integer_value(&variables, src2);
+            if num2 < 0 {
+                let e = format!("Runtime Error. Negative exponent {} in an int power. Use floats instead.", num2);
+                return error(MAX_LINE, e);
+            }
+            let result = match num1.checked_pow(num2 as u32) {
+                Some(result) => result,
+                None => {
+                    let e = format!("Runtime Error. {} to the power of {} does not fit in an int.", num1, num2);
+                    return error(MAX_LINE, e);
+                }
+            };
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(result);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FPow(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num1.powf(num2));
+            instr_pointer += 1;
+        }
+
+        Bytecode::Sqrt(dest, src) => {
+            let num = read_float_value(&variables, src);
+            if num < 0.0 {
+                let e = format!("Runtime Error. Square root of the negative number {:?}.", num);
+                return error(MAX_LINE, e);
+            }
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num.sqrt());
+            instr_pointer += 1;
+        }
+
+        Bytecode::Gcd(dest, src1, src2) => {
+            let (a, b) = (read_integer_value(&variables, src1), read_integer_value(&variables, src2));
+            let mut num1 = a.unsigned_abs();
+            let mut num2 = b.unsigned_abs();
+            while num2 != 0 {
+                let rem = num1 % num2;
+                num1 = num2;
+                num2 = rem;
+            }
+            // gcd(-2147483648, 0) is 2147483648, one more than the largest int.
+            let result = match i32::try_from(num1) {
+                Ok(result) => result,
+                Err(_) => {
+                    let e = format!("Runtime Error. The gcd of {} and {} does not fit in an int.", a, b);
+                    return error(MAX_LINE, e);
+                }
+            };
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(result);
+            instr_pointer += 1;
+        }
+
+        Bytecode::Rand(dest) => {
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(runtime.random.next());
+            instr_pointer += 1;
+        }
+
+        Bytecode::Arg(dest, index) => {
+            let index = read_integer_value(&variables, index);
+            let args = &runtime.args;
+            if index < 0 || index as usize >= args.len() {
+                let e = format!("Runtime Error. Argument {} does not exist, the program was given {} arguments.", index, args.len());
+                return error(MAX_LINE, e);
+            }
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(args[index as usize]);
+            instr_pointer += 1;
+        }
+
+        Bytecode::Srand(seed) => {
+            let seed = read_integer_value(&variables, seed);
+            runtime.random.seed(seed as u64);
+            instr_pointer += 1;
+        }
+
+        Bytecode::IntToFloat(dest, src) => {
+            let num = read_integer_value(&variables, src);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Float(num as f64);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FloatToInt(dest, src) => {
+            // truncates towards zero.
+            let num = read_float_value(&variables, src);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int(num as i32);
             instr_pointer += 1;
         }
 
@@ -472,7 +985,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 < num2) as i32;
+            *dest = Value::Int((num1 < num2) as i32);
             instr_pointer += 1;
         }
 
@@ -480,7 +993,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 <= num2) as i32;
+            *dest = Value::Int((num1 <= num2) as i32);
             instr_pointer += 1;
         }
 
@@ -488,7 +1001,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 == num2) as i32;
+            *dest = Value::Int((num1 == num2) as i32);
             instr_pointer += 1;
         }
 
@@ -496,7 +1009,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 != num2) as i32;
+            *dest = Value::Int((num1 != num2) as i32);
             instr_pointer += 1;
         }
 
@@ -504,7 +1017,7 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 > num2) as i32;
+            *dest = Value::Int((num1 > num2) as i32);
             instr_pointer += 1;
         }
 
@@ -512,7 +1025,55 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             let num1 = read_integer_value(&variables, src1);
             let num2 = read_integer_value(&variables, src2);
             let dest = variables.get_mut(dest).unwrap();
-            *dest = (num1 >= num2) as i32;
+            *dest = Value::Int((num1 >= num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FLessThan(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 < num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FLessEqual(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 <= num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FEqual(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 == num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FNotEqual(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 != num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FGreaterThan(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 > num2) as i32);
+            instr_pointer += 1;
+        }
+
+        Bytecode::FGreaterEqual(dest, src1, src2) => {
+            let num1 = read_float_value(&variables, src1);
+            let num2 = read_float_value(&variables, src2);
+            let dest = variables.get_mut(dest).unwrap();
+            *dest = Value::Int((num1 >= num2) as i32);
             instr_pointer += 1;
         }
 
@@ -548,37 +1109,160 @@ fn run_bytecode(stdin: &io::Stdin, function: &FunctionBytecode, calls: &Vec<Func
             }
         }
 
-        Bytecode::Call(dest, function_index, parameters) => {
-             let function = &calls[*function_index];
+        Bytecode::Bounds(array, dimension, index, length) => {
+            let index = read_integer_value(&variables, index);
+            let length = read_integer_value(&variables, length);
+            if index < 0 || index >= length {
+                let e = format!("Runtime Error: Array '{}' out of bounds in dimension {}. Index {}. Dimension Length {}.", array, dimension, index, length);
+                return error(MAX_LINE, e);
+            }
+            instr_pointer += 1;
+        }
+
+        Bytecode::Assert(condition, line) => {
+            if read_integer_value(&variables, condition) == 0 {
+                let e = format!("Runtime Error. Assertion failed in function '{}' at line {}.", function.name, line);
+                return error(MAX_LINE, e);
+            }
+            instr_pointer += 1;
+        }
+
+        Bytecode::Exit(code) => {
+            return Err(Trap::Exit(read_integer_value(&variables, code)));
+        }
+
+        // references are stored as the function index + 1, so a variable that was never assigned is 0.
+        Bytecode::FuncRef(dest, function_index) => {
+             let dest = variables.get_mut(dest).unwrap();
+             *dest = Value::Int(*function_index as i32 + 1);
+             instr_pointer += 1;
+        }
+
+        Bytecode::Call(_, _, _) | Bytecode::CallIndirect(_, _, _) => {
+             let (dests, function, parameters) = match instr {
+             Bytecode::Call(dests, function_index, parameters) => (dests, &calls[*function_index], parameters),
+             Bytecode::CallIndirect(dests, reference, parameters) => {
+                 let reference = read_integer_value(&variables, &Op::Var(*reference));
+                 // references start at 1, 0 is a reference that was never assigned.
+                 let function = match usize::try_from(reference).ok().and_then(|reference| reference.checked_sub(1)).and_then(|id| calls.get(id)) {
+                 Some(function) => function,
+                 None => return error(MAX_LINE, String::from("Runtime Error. Call through a function reference that was never assigned.")),
+                 };
+                 if parameters.len() != function.parameters {
+                     let e = format!("Runtime Error. Function '{}' takes {} parameters, but {} were passed through a function reference.", function.name, function.parameters, parameters.len());
+                     return error(MAX_LINE, e);
+                 }
+                 (dests, function, parameters)
+             }
+             _ => unreachable!(),
+             };
              let mut pass = vec![];
              for p in parameters {
-                  let num1: i32 = read_integer_value(&variables, p);
-                  pass.push(num1);
+                  match p {
+                  Param::Value(op) => pass.push(Argument::Value(read_value(&variables, op))),
+                  Param::Array(id) => pass.push(Argument::Array(Rc::clone(get_array(&arrays, id)?))),
+                  }
              }
 
-             let eax = run_bytecode(stdin, function, calls, &pass)?;
-             let dest = variables.get_mut(dest).unwrap();
-             *dest = eax;
+             let values = run_bytecode(runtime, function, calls, &pass)?;
+             if values.len() != dests.len() {
+                 let e = format!("Runtime Error. Function '{}' returned {} values, but {} were expected.", function.name, values.len(), dests.len());
+                 return error(MAX_LINE, e);
+             }
+             for (dest_id, eax) in dests.iter().zip(values) {
+                 match (variables.get_mut(dest_id), eax) {
+                 // arrays are returned into array variables, which are not in 'variables'.
+                 (None, Value::Handle(handle)) => bind(&mut arrays, *dest_id, handle),
+                 // an int return value is widened when the destination is a float.
+                 (Some(dest @ Value::Float(_)), Value::Int(num)) => *dest = Value::Float(num as f64),
+                 (Some(dest), eax) if dest.same_type(&eax) => *dest = eax,
+                 (dest, eax) => {
+                     let dest_type = dest.map_or("an array", |dest| dest.type_name());
+                     let e = format!("Runtime Error. Function '{}' returned {}, but the destination is {}.", function.name, eax.type_name(), dest_type);
+                     return error(MAX_LINE, e);
+                 }
+                 }
+             }
              instr_pointer += 1;
         }
 
-        Bytecode::Return(src1) => {
-            let num = read_integer_value(&variables, src1);
-            return Ok(num);
+        Bytecode::Return(srcs) => {
+            let values = srcs.iter().map(|src| match src {
+                Param::Value(op) => read_value(&variables, op),
+                Param::Array(id) => Value::Handle(arrays.get(id).cloned()),
+            }).collect();
+            return Ok(values);
         }
 
         }
     }
 
-    return Ok(0);
+    Ok(vec![Value::Int(0)])
+}
+
+// makes an array variable refer to another array, or to none at all.
+fn bind(arrays: &mut HashMap<i32, Array>, id: i32, handle: Option<Array>) {
+    match handle {
+    Some(array) => arrays.insert(id, array),
+    None => arrays.remove(&id),
+    };
+}
+
+// '%int[] name' without a size has no array until one is assigned to it.
+fn get_array<'a>(arrays: &'a HashMap<i32, Array>, id: &i32) -> Result<&'a Array, IRError> {
+    match arrays.get(id) {
+    Some(array) => Ok(array),
+    None => error(MAX_LINE, String::from("Runtime Error. Array used before an array was assigned to it.")),
+    }
+}
+
+// total number of elements of a (flattened) array, checking that every dimension is positive.
+fn array_length(variables: &HashMap<i32, Value>, dims: &[Op]) -> Result<usize, IRError> {
+    let mut len: i32 = 1;
+    for (i, size) in dims.iter().enumerate() {
+        let size = read_integer_value(variables, size);
+        if size <= 0 {
+            let e = format!("Runtime Error. Array size must be positive. Dimension {} has size {}.", i + 1, size);
+            return error(MAX_LINE, e);
+        }
+        len = match len.checked_mul(size) {
+            Some(len) => len,
+            None => return error(MAX_LINE, String::from("Runtime Error. Array is too large.")),
+        };
+    }
+    Ok(len as usize)
 }
 
 fn lookup_integer_variable_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<Op, IRError> {
+    Ok(Op::Var(lookup_variable_dest_id(line, function, ident)?))
+}
+
+fn lookup_variable_dest_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<i32, IRError> {
+    lookup_typed_variable_id(line, function, ident, ValueType::Int)
+}
+
+fn lookup_float_variable_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<Op, IRError> {
+    Ok(Op::Var(lookup_typed_variable_id(line, function, ident, ValueType::Float)?))
+}
+
+fn lookup_typed_variable_id(line: usize, function: &FunctionBytecode, ident: &String, value_type: ValueType) -> Result<i32, IRError> {
+    let (id, found) = lookup_scalar_variable_id(line, function, ident)?;
+    if found != value_type {
+        let f = format!("invalid operand. '{}' is {}, not {}.", ident, found, value_type);
+        return error(line, f);
+    }
+    Ok(id)
+}
+
+// an int or a float variable.
+fn lookup_scalar_variable_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<(i32, ValueType), IRError> {
     if let Some(id) = function.variables.get(ident) {
          match id {
-         VariableType::IntVar(id) => Ok(Op::Var(*id)),
+         VariableType::Int(id) => Ok((*id, ValueType::Int)),
+
+         VariableType::Float(id) => Ok((*id, ValueType::Float)),
 
-         VariableType::ArrayVar(_,_) => {
+         VariableType::Array(_,_) | VariableType::FloatArray(_,_) | VariableType::HandleArray(_,_,_) => {
              let f = format!("invalid operand. '{}' is an array, not an integer.", ident);
              return error(line, f);
          }
@@ -590,23 +1274,48 @@ fn lookup_integer_variable_id(line: usize, function: &FunctionBytecode, ident: &
     }
 }
 
-fn lookup_variable_dest_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<i32, IRError> {
+fn lookup_array_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<(i32, ValueType), IRError> {
     if let Some(id) = function.variables.get(ident) {
          match id {
-         VariableType::IntVar(id) => Ok(*id),
+         VariableType::Array(id,_) => Ok((*id, ValueType::Int)),
 
-         VariableType::ArrayVar(_,_) => {
-             let f = format!("invalid operand. '{}' is an array, not an integer.", ident);
+         VariableType::FloatArray(id,_) => Ok((*id, ValueType::Float)),
+
+         VariableType::HandleArray(_,_,_) => {
+             let f = format!("invalid instruction. {} is an array of arrays, move one of its arrays into an array variable first.", ident);
+             return error(line, f);
+         }
+
+         VariableType::Int(_) | VariableType::Float(_) => {
+             let f = format!("invalid '%mov' statement. {} is an integer, not an array.", ident);
              return error(line, f);
          }
 
          }
     } else {
-         let f = format!("invalid instruction. identifier '{}' has not been declared.", ident);
+         let f = format!("invalid instruction. no such identifier '{}'.", ident);
          return error(line, f);
     }
 }
 
+// any array, including arrays of arrays, for instructions that don't touch the elements.
+fn lookup_any_array_id(line: usize, function: &FunctionBytecode, ident: &String) -> Result<i32, IRError> {
+    match function.variables.get(ident) {
+    Some(VariableType::HandleArray(id,_,_)) => Ok(*id),
+    _ => lookup_array_id(line, function, ident).map(|(id, _)| id),
+    }
+}
+
+// an int or float variable, or a literal.
+fn value_operand(line: usize, function: &FunctionBytecode, token: &IRTok) -> Option<Result<(Op, ValueType), IRError>> {
+    match token {
+    IRTok::Var(ident) => Some(lookup_scalar_variable_id(line, function, ident).map(|(id, value_type)| (Op::Var(id), value_type))),
+    IRTok::Num(num) => Some(Ok((Op::Num(*num), ValueType::Int))),
+    IRTok::FloatNum(num) => Some(Ok((Op::Float(*num), ValueType::Float))),
+    _ => None,
+    }
+}
+
 fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut FunctionBytecode, labels_hash: &mut HashMap<String, usize>, tokens: &Vec<IRTok>, idx: &mut usize) -> Result<Bytecode, IRError> {
 
     // get rid of newlines.
@@ -636,32 +1345,73 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
              return error(*serialized_line, f);
         } else {
              let id = get_id(function);
-             function.variables.insert(ident.clone(), VariableType::IntVar(id));
+             function.variables.insert(ident.clone(), VariableType::Int(id));
              bytecode = Bytecode::Int(id);
         }
     }
 
-    IRTok::IntArray => {
+    IRTok::Float => {
         *idx += 1;
         let ident = match next_result(*serialized_line, tokens, idx)? {
         IRTok::Var(ident) => ident,
-        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'")),
+        _ => return error(*serialized_line, String::from("invalid instruction. expected identifier like '%float variable'")),
         };
 
-        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
-            return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'"));
+        if let Some(_) = function.variables.get(ident) {
+             let f = format!("invalid instruction. identifier '{}' declared too many times", ident);
+             return error(*serialized_line, f);
+        } else {
+             let id = get_id(function);
+             function.variables.insert(ident.clone(), VariableType::Float(id));
+             bytecode = Bytecode::Float(id);
         }
+    }
 
-        let num = match next_result(*serialized_line, tokens, idx)? {
-        IRTok::Num(num) => *num,
-        IRTok::Var(ident) => {
-          return error(*serialized_line, format!("array cannot be a variable '{ident}' length. must be a number."));
-        }
-        _ => {return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'"));}
+    IRTok::IntArray | IRTok::FloatArray | IRTok::IntHandleArray | IRTok::FloatHandleArray => {
+        let is_float = matches!(opcode, IRTok::FloatArray | IRTok::FloatHandleArray);
+        let holds_arrays = matches!(opcode, IRTok::IntHandleArray | IRTok::FloatHandleArray);
+        *idx += 1;
+        let ident = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => ident,
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'")),
         };
 
-        if num <= 0 {
-          return error(*serialized_line, String::from("array size cannot be less than or equal to zero."));
+        // one size per dimension, '%int[] array, 10' or '%int[] matrix, rows, 4'.
+        // sizes given as variables are only known when the instruction runs.
+        // '%int[] array' without a size refers to no array until one is assigned with %new, %mov or %call.
+        let mut dims = vec![];
+        if holds_arrays || !matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::EndInstr) {
+            if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+                return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'"));
+            }
+
+            loop {
+                let size = match next_result(*serialized_line, tokens, idx)? {
+                IRTok::Num(num) => {
+                    if *num <= 0 {
+                      return error(*serialized_line, String::from("array size cannot be less than or equal to zero."));
+                    }
+                    Op::Num(*num)
+                }
+                IRTok::Var(ident) => lookup_integer_variable_id(*serialized_line, function, ident)?,
+                _ => {return error(*serialized_line, String::from("invalid instruction. expected format like '%int[] array, 10'"));}
+                };
+                dims.push(size);
+
+                if !matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::Comma) {
+                    break;
+                }
+                *idx += 1;
+            }
+        }
+
+        // arrays of a constant size are allocated when the function is entered, like before.
+        let mut static_len: i32 = 1;
+        for size in &dims {
+            static_len = match size {
+            Op::Num(num) => static_len.saturating_mul(*num),
+            _ => 0,
+            };
         }
 
         if let Some(_) = function.variables.get(ident) {
@@ -669,24 +1419,94 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
              return error(*serialized_line, f);
         } else {
              let id = get_id(function);
-             function.variables.insert(ident.clone(), VariableType::ArrayVar(id, num));
-             bytecode = Bytecode::IntArray(id, num);
+             if holds_arrays {
+                 let element_type = if is_float { ValueType::Float } else { ValueType::Int };
+                 function.variables.insert(ident.clone(), VariableType::HandleArray(id, static_len, element_type));
+                 bytecode = Bytecode::HandleArray(id, dims);
+             } else if dims.is_empty() {
+                 if is_float {
+                     function.variables.insert(ident.clone(), VariableType::FloatArray(id, 0));
+                 } else {
+                     function.variables.insert(ident.clone(), VariableType::Array(id, 0));
+                 }
+                 bytecode = Bytecode::ArrayRef(id);
+             } else if is_float {
+                 function.variables.insert(ident.clone(), VariableType::FloatArray(id, static_len));
+                 bytecode = Bytecode::FloatArray(id, dims);
+             } else {
+                 function.variables.insert(ident.clone(), VariableType::Array(id, static_len));
+                 bytecode = Bytecode::IntArray(id, dims);
+             }
+        }
+    }
+
+    // %new array, size makes 'array' refer to a new array of 'size' zeroes.
+    IRTok::New => {
+        *idx += 1;
+        let format = "invalid instruction. expected format like '%new array, size'";
+        let (dest, element_type) = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => lookup_array_id(*serialized_line, function, ident)?,
+        _ => return error(*serialized_line, String::from(format)),
+        };
+        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(*serialized_line, String::from(format));
         }
+        let size = typed_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?, ValueType::Int, format)?;
+        bytecode = Bytecode::New(dest, size, element_type);
     }
 
     // function calling routines.
-    IRTok::Call => {
+    // %funcref dest, function
+    IRTok::FuncRef => {
         *idx += 1;
-        let op = match next_result(*serialized_line, tokens, idx)? {
+        let dest = match next_result(*serialized_line, tokens, idx)? {
         IRTok::Var(ident) => lookup_variable_dest_id(*serialized_line, function, ident)?,
-        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%call value, function(a,b)'")),
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%funcref dest, function'")),
         };
-
         if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
-            return error(*serialized_line, String::from("invalid instruction. missing comma ',' in between '%call value, function(a,b)'"));
+            return error(*serialized_line, String::from("invalid instruction. expected format like '%funcref dest, function'"));
+        }
+        if !matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::Var(_)) {
+            return error(*serialized_line, String::from("invalid instruction. expected format like '%funcref dest, function'"));
+        }
+        // the name is resolved to the function once every function is parsed.
+        bytecode = Bytecode::FuncRef(dest, *idx);
+        *idx += 1;
+    }
+
+    // %call value, function(a,b) or %call q, r, function(a,b) for several return values.
+    // %icall value, reference(a,b) calls the function stored in the variable 'reference'.
+    // a returned array is assigned to an array variable, '%call array, function(a,b)'.
+    IRTok::Call | IRTok::CallIndirect => {
+        let indirect = matches!(tokens[*idx], IRTok::CallIndirect);
+        *idx += 1;
+        let mut dests = vec![];
+        loop {
+            let op = match next_result(*serialized_line, tokens, idx)? {
+            IRTok::Var(ident) => match function.variables.get(ident) {
+                Some(VariableType::Array(id,_) | VariableType::FloatArray(id,_)) => *id,
+                _ => lookup_scalar_variable_id(*serialized_line, function, ident)?.0,
+            },
+            _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%call value, function(a,b)'")),
+            };
+            dests.push(op);
+
+            if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+                return error(*serialized_line, String::from("invalid instruction. missing comma ',' in between '%call value, function(a,b)'"));
+            }
+
+            // the function name is the identifier followed by '('.
+            if matches!(tokens.get(*idx + 1), Some(IRTok::LParen)) {
+                break;
+            }
         }
 
         let func_id = match peek_result(*serialized_line, tokens, *idx)? {
+        IRTok::Var(ident) if indirect => {
+            let reference = lookup_variable_dest_id(*serialized_line, function, ident)?;
+            *idx += 1;
+            reference as usize
+        }
         IRTok::Var(_) => {
             let func_id = *idx;
             *idx += 1;
@@ -703,23 +1523,26 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
         loop {
             match peek_result(*serialized_line, tokens, *idx)? {
             IRTok::RParen => break,
-            IRTok::Var(ident) => {
+            IRTok::Var(ident) if matches!(function.variables.get(ident), Some(VariableType::Array(_,_) | VariableType::FloatArray(_,_) | VariableType::HandleArray(_,_,_))) => {
+                let id = lookup_any_array_id(*serialized_line, function, ident)?;
                 *idx += 1;
-                let param = lookup_integer_variable_id(*serialized_line, function, ident)?;
-                parameters.push(param);
+                parameters.push(Param::Array(id));
                 if matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::Comma) {
                     *idx += 1;
                 }
             }
 
-            IRTok::Num(num) => {
+            token => {
+                let param = match value_operand(*serialized_line, function, token) {
+                Some(param) => Param::Value(param?.0),
+                None => return error(*serialized_line, String::from("invalid calling convention. must be in the format '%call value, function(a,b)'")),
+                };
                 *idx += 1;
-                parameters.push(Op::Num(*num));
+                parameters.push(param);
                 if matches!(peek_result(*serialized_line, tokens, *idx)?, IRTok::Comma) {
                     *idx += 1;
                 }
             }
-            _ => return error(*serialized_line, String::from("invalid calling convention. must be in the format '%call value, function(a,b)'")),
 
             }
         }
@@ -727,57 +1550,161 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
         if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::RParen) {
             return error(*serialized_line, String::from("invalid instruction. missing ')' in between '%call value, function(a,b)'"));
         }
-        bytecode = Bytecode::Call(op, func_id, parameters);
+        if indirect {
+            bytecode = Bytecode::CallIndirect(dests, func_id as i32, parameters);
+        } else {
+            bytecode = Bytecode::Call(dests, func_id, parameters);
+        }
     }
 
+    // %ret value or %ret a, b
+    // '%ret array' returns the array itself, not a copy.
     IRTok::Return => {
         *idx += 1;
-        let op = match next_result(*serialized_line, tokens, idx)? {
-        IRTok::Var(ident) => lookup_integer_variable_id(*serialized_line, function, ident)?,
-        IRTok::Num(num) => Op::Num(*num),
-        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%ret variable'")),
+        let mut ops = vec![];
+        loop {
+            let op = match next_result(*serialized_line, tokens, idx)? {
+            IRTok::Var(ident) if matches!(function.variables.get(ident), Some(VariableType::Array(_,_) | VariableType::FloatArray(_,_))) => {
+                Param::Array(lookup_array_id(*serialized_line, function, ident)?.0)
+            }
+            token => match value_operand(*serialized_line, function, token) {
+                Some(op) => Param::Value(op?.0),
+                None => return error(*serialized_line, String::from("invalid instruction. expected format like '%ret variable'")),
+            },
+            };
+            ops.push(op);
+            if !matches!(peek(tokens, *idx), Some(IRTok::Comma)) {
+                break;
+            }
+            *idx += 1;
+        }
+        bytecode = Bytecode::Return(ops);
+    }
+
+    // %len dest, array
+    IRTok::Len => {
+        *idx += 1;
+        let dest = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => lookup_variable_dest_id(*serialized_line, function, ident)?,
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%len dest, array'")),
         };
-        bytecode = Bytecode::Return(op);
+
+        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(*serialized_line, String::from("invalid instruction. expected format like '%len dest, array'"));
+        }
+
+        let array = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => lookup_any_array_id(*serialized_line, function, ident)?,
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%len dest, array'")),
+        };
+        bytecode = Bytecode::Len(dest, array);
     }
 
     // input/output routines.
     IRTok::Out => {
         *idx += 1;
-        let src = match next_result(*serialized_line, tokens, idx)? {
-        IRTok::Var(ident) => lookup_integer_variable_id(*serialized_line, function, ident)?,
-        IRTok::Num(num) => Op::Num(*num),
-        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%out variable'")),
+        let src = match value_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?) {
+        Some(src) => src?.0,
+        None => return error(*serialized_line, String::from("invalid instruction. expected format like '%out variable'")),
         };
         bytecode = Bytecode::Out(src);
     }
 
-    IRTok::In => {
+    // %out_str "text" and %out_val value print without a newline.
+    IRTok::OutStr => {
         *idx += 1;
-        let src = match next_result(*serialized_line, tokens, idx)? {
+        let text = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Str(text) => text.clone(),
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%out_str \"text\"'")),
+        };
+        bytecode = Bytecode::OutStr(text);
+    }
+
+    IRTok::OutVal => {
+        *idx += 1;
+        let src = match value_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?) {
+        Some(src) => src?.0,
+        None => return error(*serialized_line, String::from("invalid instruction. expected format like '%out_val variable'")),
+        };
+        bytecode = Bytecode::OutVal(src);
+    }
+
+    // %getc dest, %putc src, %eof dest
+    IRTok::Getc | IRTok::Eof => {
+        let is_getc = matches!(tokens[*idx], IRTok::Getc);
+        *idx += 1;
+        let dest = match next_result(*serialized_line, tokens, idx)? {
         IRTok::Var(ident) => lookup_variable_dest_id(*serialized_line, function, ident)?,
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%getc dest' or '%eof dest'")),
+        };
+        bytecode = if is_getc { Bytecode::Getc(dest) } else { Bytecode::Eof(dest) };
+    }
+
+    IRTok::Putc => {
+        *idx += 1;
+        let src = typed_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?, ValueType::Int, "invalid instruction. expected format like '%putc value'")?;
+        bytecode = Bytecode::Putc(src);
+    }
+
+    IRTok::In => {
+        *idx += 1;
+        let dest = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => match lookup_scalar_variable_id(*serialized_line, function, ident)? {
+            (id, ValueType::Float) => MemWrite::FloatVar(id),
+            (id, _) => MemWrite::IntVar(id),
+        },
+
+        // %input [array + index]
+        IRTok::LBrace => {
+            let e = "invalid instruction. expected format like '%input [array + index]'";
+            let array = match next_result(*serialized_line, tokens, idx)? {
+            IRTok::Var(ident) => lookup_array_id(*serialized_line, function, ident)?.0,
+            _ => return error(*serialized_line, String::from(e)),
+            };
+            if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Plus) {
+                return error(*serialized_line, String::from(e));
+            }
+            let index = match next_result(*serialized_line, tokens, idx)? {
+            IRTok::Var(ident) => lookup_integer_variable_id(*serialized_line, function, ident)?,
+            IRTok::Num(num) => Op::Num(*num),
+            _ => return error(*serialized_line, String::from(e)),
+            };
+            if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::RBrace) {
+                return error(*serialized_line, String::from(e));
+            }
+            MemWrite::ArrayWrite(array, index)
+        }
+
         _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%input variable'")),
         };
-        bytecode = Bytecode::In(src);
+        bytecode = Bytecode::In(dest);
     }
 
     // mathematical operators.
+    IRTok::Mov if is_array_mov(function, tokens, *idx + 1) => {
+        *idx += 1;
+        bytecode = parse_array_mov(*serialized_line, function, tokens, idx)?;
+    }
+
     IRTok::Mov => {
         *idx += 1;
         match next_result(*serialized_line, tokens, idx)? {
         IRTok::Var(ident) => {
-            let dest = lookup_variable_dest_id(*serialized_line, function, ident)?;
+            let (dest, dest_type) = lookup_scalar_variable_id(*serialized_line, function, ident)?;
 
             if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
                 return error(*serialized_line, String::from("invalid instruction. missing comma. expected format like '%mov variable, 10'"));
             }
             
-            let src = match next_result(*serialized_line, tokens, idx)? {
+            let (src, src_type) = match next_result(*serialized_line, tokens, idx)? {
             IRTok::Var(ident) => {
                 if let Some(id) = function.variables.get(ident) {
                      match id {
-                     VariableType::IntVar(id) => MemRead::IntVar(*id),
+                     VariableType::Int(id) => (MemRead::IntVar(*id), ValueType::Int),
+
+                     VariableType::Float(id) => (MemRead::FloatVar(*id), ValueType::Float),
          
-                     VariableType::ArrayVar(_,_) => {
+                     VariableType::Array(_,_) | VariableType::FloatArray(_,_) | VariableType::HandleArray(_,_,_) => {
                          let f = format!("invalid '%mov' statement. {} is an array, not an integer.", ident);
                          return error(*serialized_line, f);
                      }
@@ -790,42 +1717,15 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
             }
 
             IRTok::LBrace => {
-                match (next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?) {
-                (IRTok::Var(array), IRTok::Plus, IRTok::Num(index), IRTok::RBrace) => {
-                    if let Some(id) = function.variables.get(array) {
-                        match id {
-                        VariableType::ArrayVar(id,_) => {
-                            MemRead::ArrayRead(*id, Op::Num(*index))
-                        }
-                        _ => {
-                           let f = format!("invalid '%mov' statement. {} is an integer, not an array.", array);
-                           return error(*serialized_line, f);
-                        }
-
-                        }
-                    } else {
-                        let f = format!("invalid instruction. no such identifier '{}'.", array);
-                        return error(*serialized_line, f);
-                    }
+                match (next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?, next_result(*serialized_line, tokens,idx)?) {
+                (IRTok::Var(array), IRTok::Plus, IRTok::Num(index), IRTok::RBrace) => {
+                    let (id, elem_type) = lookup_array_id(*serialized_line, function, array)?;
+                    (MemRead::ArrayRead(id, Op::Num(*index)), elem_type)
                 }
 
                 (IRTok::Var(array), IRTok::Plus, IRTok::Var(variable), IRTok::RBrace) => {
-                    if let Some(id) = function.variables.get(array) {
-                        match id {
-                        VariableType::ArrayVar(id,_) => {
-                            
-                            MemRead::ArrayRead(*id, lookup_integer_variable_id(*serialized_line, function, variable)?)
-                        }
-                        _ => {
-                           let f = format!("invalid '%mov' statement. {} is an integer, not an array.", array);
-                           return error(*serialized_line, f);
-                        }
-
-                        }
-                    } else {
-                        let f = format!("invalid instruction. no such identifier '{}'.", array);
-                        return error(*serialized_line, f);
-                    }
+                    let (id, elem_type) = lookup_array_id(*serialized_line, function, array)?;
+                    (MemRead::ArrayRead(id, lookup_integer_variable_id(*serialized_line, function, variable)?), elem_type)
                 }
 
                 _ => return error(*serialized_line, String::from("invalid '%mov' statement. expected '%mov var, [array + index]'")),
@@ -834,27 +1734,43 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
 
             }
 
-            IRTok::Num(num) => MemRead::Number(*num),
+            IRTok::Num(num) => (MemRead::Number(*num), ValueType::Int),
+            IRTok::FloatNum(num) => (MemRead::Float(*num), ValueType::Float),
             _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%mov variable, 10'")),
             };
 
-            bytecode = Bytecode::Mov(MemWrite::IntVar(dest), src);
+            if src_type != dest_type {
+                let f = format!("invalid '%mov' statement. cannot move {} into {} variable '{}'.", src_type, dest_type, ident);
+                return error(*serialized_line, f);
+            }
+
+            if dest_type == ValueType::Float {
+                bytecode = Bytecode::Mov(MemWrite::FloatVar(dest), src);
+            } else {
+                bytecode = Bytecode::Mov(MemWrite::IntVar(dest), src);
+            }
         }
 
         IRTok::LBrace => {
-            let dest = match next_result(*serialized_line, tokens, idx)? {
+            let (dest, elem_type) = match next_result(*serialized_line, tokens, idx)? {
             IRTok::Var(ident) => {
                 if let Some(id) = function.variables.get(ident) {
                     match id {
-                    VariableType::IntVar(_) => {
+                    VariableType::Int(_) | VariableType::Float(_) => {
                         let f = format!("invalid '%mov' statement. {} is an array, not an integer.", ident);
                         return error(*serialized_line, f);
                     }
          
-                    VariableType::ArrayVar(dest,_) => {
-                        *dest
+                    VariableType::Array(dest,_) => {
+                        (*dest, ValueType::Int)
+                    }
+
+                    VariableType::FloatArray(dest,_) => {
+                        (*dest, ValueType::Float)
                     }
 
+                    VariableType::HandleArray(_,_,_) => unreachable!("stores into arrays of arrays are parsed by parse_array_mov"),
+
                     }
                 } else {
                     return error(*serialized_line, String::from("invalid instruction. expected format like '%mov [array + 10], 10'"));
@@ -882,13 +1798,15 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
                 return error(*serialized_line, String::from("invalid instruction. expected format like '%mov [array + 10], 10'"));
             }
 
-            let src = match next_result(*serialized_line, tokens, idx)? {
+            let (src, src_type) = match next_result(*serialized_line, tokens, idx)? {
             IRTok::Var(ident) => {
                 if let Some(id) = function.variables.get(ident) {
                      match id {
-                     VariableType::IntVar(id) => MemRead::IntVar(*id),
+                     VariableType::Int(id) => (MemRead::IntVar(*id), ValueType::Int),
+
+                     VariableType::Float(id) => (MemRead::FloatVar(*id), ValueType::Float),
          
-                     VariableType::ArrayVar(_,_) => {
+                     VariableType::Array(_,_) | VariableType::FloatArray(_,_) | VariableType::HandleArray(_,_,_) => {
                          let f = format!("invalid '%mov' statement. {} is an array, not an integer.", ident);
                          return error(*serialized_line, f);
                      }
@@ -900,10 +1818,16 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
                 }
             }
 
-            IRTok::Num(num) => MemRead::Number(*num),
+            IRTok::Num(num) => (MemRead::Number(*num), ValueType::Int),
+            IRTok::FloatNum(num) => (MemRead::Float(*num), ValueType::Float),
             _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%mov variable, 10'")),
             };
 
+            if src_type != elem_type {
+                let f = format!("invalid '%mov' statement. cannot move {} into an array of {}.", src_type, elem_type);
+                return error(*serialized_line, f);
+            }
+
             bytecode = Bytecode::Mov(MemWrite::ArrayWrite(dest, index), src);
         }
 
@@ -942,6 +1866,126 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
         bytecode = Bytecode::Mod(dest, src1, src2);
     }
 
+    IRTok::FAdd => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FAdd(dest, src1, src2);
+    }
+
+    IRTok::FSub => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FSub(dest, src1, src2);
+    }
+
+    IRTok::FMult => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FMult(dest, src1, src2);
+    }
+
+    IRTok::FDiv => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FDiv(dest, src1, src2);
+    }
+
+    // math library.
+    IRTok::Abs => {
+        *idx += 1;
+        let (dest, src) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Int)?;
+        bytecode = Bytecode::Abs(dest, src);
+    }
+
+    IRTok::FAbs => {
+        *idx += 1;
+        let (dest, src) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FAbs(dest, src);
+    }
+
+    IRTok::Min => {
+        *idx += 1;
+        let (dest, src1, src2) = addr_code3(*serialized_line, function, tokens, idx)?;
+        bytecode = Bytecode::Min(dest, src1, src2);
+    }
+
+    IRTok::FMin => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FMin(dest, src1, src2);
+    }
+
+    IRTok::Max => {
+        *idx += 1;
+        let (dest, src1, src2) = addr_code3(*serialized_line, function, tokens, idx)?;
+        bytecode = Bytecode::Max(dest, src1, src2);
+    }
+
+    IRTok::FMax => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FMax(dest, src1, src2);
+    }
+
+    IRTok::Pow => {
+        *idx += 1;
+        let (dest, src1, src2) = addr_code3(*serialized_line, function, tokens, idx)?;
+        bytecode = Bytecode::Pow(dest, src1, src2);
+    }
+
+    IRTok::FPow => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::FPow(dest, src1, src2);
+    }
+
+    IRTok::Sqrt => {
+        *idx += 1;
+        let (dest, src) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Float)?;
+        bytecode = Bytecode::Sqrt(dest, src);
+    }
+
+    IRTok::Gcd => {
+        *idx += 1;
+        let (dest, src1, src2) = addr_code3(*serialized_line, function, tokens, idx)?;
+        bytecode = Bytecode::Gcd(dest, src1, src2);
+    }
+
+    IRTok::Rand => {
+        *idx += 1;
+        let dest = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => lookup_variable_dest_id(*serialized_line, function, ident)?,
+        _ => return error(*serialized_line, String::from("invalid instruction. expected format like '%rand dest'")),
+        };
+        bytecode = Bytecode::Rand(dest);
+    }
+
+    IRTok::Srand => {
+        *idx += 1;
+        let seed = typed_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?, ValueType::Int, "invalid instruction. expected format like '%srand seed'")?;
+        bytecode = Bytecode::Srand(seed);
+    }
+
+    // %arg dest, index
+    IRTok::Arg => {
+        *idx += 1;
+        let (dest, index) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Int)?;
+        bytecode = Bytecode::Arg(dest, index);
+    }
+
+    // conversions.
+    IRTok::IntToFloat => {
+        *idx += 1;
+        let (dest, src) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Float, ValueType::Int)?;
+        bytecode = Bytecode::IntToFloat(dest, src);
+    }
+
+    IRTok::FloatToInt => {
+        *idx += 1;
+        let (dest, src) = typed_code2(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FloatToInt(dest, src);
+    }
+
     // comparison operators.
     IRTok::LessThan => {
         *idx += 1;
@@ -979,6 +2023,42 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
         bytecode = Bytecode::GreaterThan(dest, src1, src2);
     }
 
+    IRTok::FLessThan => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FLessThan(dest, src1, src2);
+    }
+
+    IRTok::FLessEqual => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FLessEqual(dest, src1, src2);
+    }
+
+    IRTok::FNotEqual => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FNotEqual(dest, src1, src2);
+    }
+
+    IRTok::FEqual => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FEqual(dest, src1, src2);
+    }
+
+    IRTok::FGreaterEqual => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FGreaterEqual(dest, src1, src2);
+    }
+
+    IRTok::FGreaterThan => {
+        *idx += 1;
+        let (dest, src1, src2) = typed_code3(*serialized_line, function, tokens, idx, ValueType::Int, ValueType::Float)?;
+        bytecode = Bytecode::FGreaterThan(dest, src1, src2);
+    }
+
     // labels/branching
     //Label,
     IRTok::Jump => {
@@ -1048,6 +2128,56 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
         }
     }
 
+    // %bounds array, dimension, index, length
+    IRTok::Bounds => {
+        *idx += 1;
+        let format = "%bounds requires the format '%bounds array, dimension, index, length'";
+        let array = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Var(ident) => {
+            lookup_array_id(*serialized_line, function, ident)?;
+            ident.clone()
+        }
+        _ => return error(*serialized_line, String::from(format)),
+        };
+
+        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(*serialized_line, String::from(format));
+        }
+
+        let dimension = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Num(num) => *num,
+        _ => return error(*serialized_line, String::from(format)),
+        };
+
+        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(*serialized_line, String::from(format));
+        }
+
+        let (index, length) = typed_code2_operands(*serialized_line, function, tokens, idx, format)?;
+        bytecode = Bytecode::Bounds(array, dimension, index, length);
+    }
+
+    // %assert condition, source line
+    IRTok::Assert => {
+        *idx += 1;
+        let format = "invalid instruction. expected format like '%assert condition, line'";
+        let condition = typed_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?, ValueType::Int, format)?;
+        if !matches!(next_result(*serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(*serialized_line, String::from(format));
+        }
+        let line = match next_result(*serialized_line, tokens, idx)? {
+        IRTok::Num(line) if *line > 0 => *line as usize,
+        _ => return error(*serialized_line, String::from(format)),
+        };
+        bytecode = Bytecode::Assert(condition, line);
+    }
+
+    IRTok::Exit => {
+        *idx += 1;
+        let code = typed_operand(*serialized_line, function, next_result(*serialized_line, tokens, idx)?, ValueType::Int, "invalid instruction. expected format like '%exit code'")?;
+        bytecode = Bytecode::Exit(code);
+    }
+
     IRTok::EndFunc => {
         bytecode = Bytecode::End;
         return Ok(bytecode);
@@ -1072,9 +2202,92 @@ fn parse_instruction(serialized_line: &mut usize, line: usize, function: &mut Fu
     return Ok(bytecode);
 }
 
+// %mov array, other, %mov array, [arrays + i] and %mov [arrays + i], array
+// make the destination refer to the same array as the source, the elements are not copied.
+fn is_array_mov(function: &FunctionBytecode, tokens: &[IRTok], idx: usize) -> bool {
+    match (tokens.get(idx), tokens.get(idx + 1)) {
+    (Some(IRTok::Var(ident)), _) => matches!(function.variables.get(ident), Some(VariableType::Array(_,_) | VariableType::FloatArray(_,_))),
+    (Some(IRTok::LBrace), Some(IRTok::Var(ident))) => matches!(function.variables.get(ident), Some(VariableType::HandleArray(_,_,_))),
+    _ => false,
+    }
+}
+
+fn parse_array_mov(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize) -> Result<Bytecode, IRError> {
+    let format = "invalid '%mov' statement. expected format like '%mov array, [arrays + index]'";
+    let check = |src_type: ValueType, dest_type: ValueType| {
+        if src_type != dest_type {
+            let f = format!("invalid '%mov' statement. cannot move {} array into {} array.", src_type, dest_type);
+            return error(serialized_line, f);
+        }
+        Ok(())
+    };
+    match next_result(serialized_line, tokens, idx)? {
+    IRTok::Var(ident) => {
+        let (dest, dest_type) = lookup_array_id(serialized_line, function, ident)?;
+        if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(serialized_line, String::from(format));
+        }
+        match next_result(serialized_line, tokens, idx)? {
+        IRTok::Var(src) => {
+            let (src, src_type) = lookup_array_id(serialized_line, function, src)?;
+            check(src_type, dest_type)?;
+            Ok(Bytecode::Bind(dest, src))
+        }
+        IRTok::LBrace => {
+            let (src, index, src_type) = parse_handle_element(serialized_line, function, tokens, idx)?;
+            check(src_type, dest_type)?;
+            Ok(Bytecode::LoadHandle(dest, MemRead::ArrayRead(src, index)))
+        }
+        _ => error(serialized_line, String::from(format)),
+        }
+    }
+    _ => {
+        let (dest, index, dest_type) = parse_handle_element(serialized_line, function, tokens, idx)?;
+        if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Comma) {
+            return error(serialized_line, String::from(format));
+        }
+        let (src, src_type) = match next_result(serialized_line, tokens, idx)? {
+        IRTok::Var(src) => lookup_array_id(serialized_line, function, src)?,
+        _ => return error(serialized_line, String::from("invalid '%mov' statement. only arrays can be stored in an array of arrays.")),
+        };
+        check(src_type, dest_type)?;
+        Ok(Bytecode::StoreHandle(MemWrite::ArrayWrite(dest, index), src))
+    }
+    }
+}
+
+// 'arrays + index]' after the '[', where 'arrays' is an array of arrays.
+fn parse_handle_element(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize) -> Result<(i32, Op, ValueType), IRError> {
+    let format = "invalid '%mov' statement. expected an element like '[arrays + index]'";
+    let (array, element_type) = match next_result(serialized_line, tokens, idx)? {
+    IRTok::Var(ident) => match function.variables.get(ident) {
+        Some(VariableType::HandleArray(id, _, element_type)) => (*id, *element_type),
+        _ => return error(serialized_line, format!("invalid '%mov' statement. {} is not an array of arrays.", ident)),
+    },
+    _ => return error(serialized_line, String::from(format)),
+    };
+    if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Plus) {
+        return error(serialized_line, String::from(format));
+    }
+    let index = match next_result(serialized_line, tokens, idx)? {
+    IRTok::Var(ident) => lookup_integer_variable_id(serialized_line, function, ident)?,
+    IRTok::Num(num) => Op::Num(*num),
+    _ => return error(serialized_line, String::from(format)),
+    };
+    if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::RBrace) {
+        return error(serialized_line, String::from(format));
+    }
+    Ok((array, index, element_type))
+}
+
 fn addr_code3(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize) -> Result<(i32, Op, Op), IRError> {
+    typed_code3(serialized_line, function, tokens, idx, ValueType::Int, ValueType::Int)
+}
+
+// 'dest, src1, src2' where dest and both sources must have the given types.
+fn typed_code3(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize, dest_type: ValueType, src_type: ValueType) -> Result<(i32, Op, Op), IRError> {
     let dest = match next_result(serialized_line, tokens, idx)? {
-    IRTok::Var(ident) => lookup_variable_dest_id(serialized_line, function, ident)?,
+    IRTok::Var(ident) => lookup_typed_variable_id(serialized_line, function, ident, dest_type)?,
     _ => return error(serialized_line, String::from("invalid dest.")),
     };
 
@@ -1082,25 +2295,55 @@ fn addr_code3(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<
         return error(serialized_line, String::from("invalid instruction. expected comma between dest and src1."));
     }
     
-    let src1 = match next_result(serialized_line, tokens, idx)? {
-    IRTok::Var(ident) => lookup_integer_variable_id(serialized_line, function, ident)?,
-    IRTok::Num(num) => Op::Num(*num),
-    _ => return error(serialized_line, String::from("invalid src1.")),
-    };
+    let src1 = typed_operand(serialized_line, function, next_result(serialized_line, tokens, idx)?, src_type, "invalid src1.")?;
 
     if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Comma) {
         return error(serialized_line, String::from("invalid instruction. expected comma between src1 and src2."));
     }
 
-    let src2 = match next_result(serialized_line, tokens, idx)? {
-    IRTok::Var(ident) => lookup_integer_variable_id(serialized_line, function, ident)?,
-    IRTok::Num(num) => Op::Num(*num),
-    _ => return error(serialized_line, String::from("invalid src2.")),
-    };
+    let src2 = typed_operand(serialized_line, function, next_result(serialized_line, tokens, idx)?, src_type, "invalid src2.")?;
 
     return Ok((dest, src1, src2));
 }
 
+// 'dest, src' where dest and src must have the given types.
+fn typed_code2(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize, dest_type: ValueType, src_type: ValueType) -> Result<(i32, Op), IRError> {
+    let dest = match next_result(serialized_line, tokens, idx)? {
+    IRTok::Var(ident) => lookup_typed_variable_id(serialized_line, function, ident, dest_type)?,
+    _ => return error(serialized_line, String::from("invalid dest.")),
+    };
+
+    if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Comma) {
+        return error(serialized_line, String::from("invalid instruction. expected comma between dest and src."));
+    }
+
+    let src = typed_operand(serialized_line, function, next_result(serialized_line, tokens, idx)?, src_type, "invalid src.")?;
+
+    Ok((dest, src))
+}
+
+// 'src1, src2' where both are ints.
+fn typed_code2_operands(serialized_line: usize, function: &FunctionBytecode, tokens: &Vec<IRTok>, idx: &mut usize, message: &str) -> Result<(Op, Op), IRError> {
+    let src1 = typed_operand(serialized_line, function, next_result(serialized_line, tokens, idx)?, ValueType::Int, message)?;
+
+    if !matches!(next_result(serialized_line, tokens, idx)?, IRTok::Comma) {
+        return error(serialized_line, String::from(message));
+    }
+
+    let src2 = typed_operand(serialized_line, function, next_result(serialized_line, tokens, idx)?, ValueType::Int, message)?;
+    Ok((src1, src2))
+}
+
+fn typed_operand(serialized_line: usize, function: &FunctionBytecode, token: &IRTok, value_type: ValueType, message: &str) -> Result<Op, IRError> {
+    match (token, value_type) {
+    (IRTok::Var(ident), ValueType::Int) => lookup_integer_variable_id(serialized_line, function, ident),
+    (IRTok::Var(ident), ValueType::Float) => lookup_float_variable_id(serialized_line, function, ident),
+    (IRTok::Num(num), ValueType::Int) => Ok(Op::Num(*num)),
+    (IRTok::FloatNum(num), ValueType::Float) => Ok(Op::Float(*num)),
+    _ => error(serialized_line, String::from(message)),
+    }
+}
+
 fn next<'a>(tokens: &'a Vec<IRTok>, index: &mut usize) -> Option<&'a IRTok> {
     if *index < tokens.len() {
         let ret = *index;
@@ -1137,7 +2380,8 @@ fn next_result<'a>(serialized_line: usize, tokens: &'a Vec<IRTok>, index: &mut u
     }
 }
 
-fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
+// also used by the compiler to check 'asm' blocks.
+pub(crate) fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
    
     #[derive(Debug)]
     enum StateMachine {
@@ -1145,7 +2389,9 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
         Lit,
         Label,
         Ident,
+        Sign,
         Num,
+        Fraction,
         Comments,
     }
 
@@ -1156,25 +2402,66 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
         "%endfunc" => Some(EndFunc),
         "%int" => Some(Int),
         "%int[]" => Some(IntArray),
+        "%float" => Some(Float),
+        "%float[]" => Some(FloatArray),
+        "%int[][]" => Some(IntHandleArray),
+        "%float[][]" => Some(FloatHandleArray),
+        "%new" => Some(New),
         "%call" => Some(Call),
+        "%icall" => Some(CallIndirect),
+        "%funcref" => Some(FuncRef),
         "%ret" => Some(Return),
         "%out" => Some(Out),
+        "%out_str" => Some(OutStr),
+        "%out_val" => Some(OutVal),
         "%input" => Some(In),
+        "%getc" => Some(Getc),
+        "%putc" => Some(Putc),
+        "%eof" => Some(Eof),
         "%mov" => Some(Mov),
         "%add" => Some(Add),
         "%sub" => Some(Sub),
         "%mult" => Some(Mult),
         "%div" => Some(Div),
         "%mod" => Some(Mod),
+        "%fadd" => Some(FAdd),
+        "%fsub" => Some(FSub),
+        "%fmult" => Some(FMult),
+        "%fdiv" => Some(FDiv),
+        "%abs" => Some(Abs),
+        "%fabs" => Some(FAbs),
+        "%min" => Some(Min),
+        "%fmin" => Some(FMin),
+        "%max" => Some(Max),
+        "%fmax" => Some(FMax),
+        "%pow" => Some(Pow),
+        "%fpow" => Some(FPow),
+        "%sqrt" => Some(Sqrt),
+        "%gcd" => Some(Gcd),
+        "%rand" => Some(Rand),
+        "%srand" => Some(Srand),
+        "%arg" => Some(Arg),
+        "%itof" => Some(IntToFloat),
+        "%ftoi" => Some(FloatToInt),
         "%lt" => Some(LessThan),
         "%le" => Some(LessEqual),
         "%neq" => Some(NotEqual),
         "%eq" => Some(Equal),
         "%gt" => Some(GreaterThan),
         "%ge" => Some(GreaterEqual),
+        "%flt" => Some(FLessThan),
+        "%fle" => Some(FLessEqual),
+        "%fneq" => Some(FNotEqual),
+        "%feq" => Some(FEqual),
+        "%fgt" => Some(FGreaterThan),
+        "%fge" => Some(FGreaterEqual),
         "%jmp" => Some(Jump),
         "%branch_if" => Some(BranchIf),
         "%branch_ifn" => Some(BranchIfNot),
+        "%bounds" => Some(Bounds),
+        "%len" => Some(Len),
+        "%assert" => Some(Assert),
+        "%exit" => Some(Exit),
         _ => Some(InvalidInstruction(String::from(s))),
         }
     }
@@ -1186,8 +2473,15 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
         }
     }
 
+    fn float_literal(s: &str) -> Option<IRTok> {
+        match s.parse::<f64>() {
+        Ok(val) => Some(IRTok::FloatNum(val)),
+        Err(_) => None,
+        }
+    }
+
     // skip left whitespace.
-    for (i, c) in code.chars().enumerate() {
+    for (i, c) in code.char_indices() {
         if c.is_whitespace() && c != '\n' {
             continue;
         }
@@ -1197,7 +2491,7 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
  
     let mut state = StateMachine::Initial;
 
-    for (i, c) in code.chars().enumerate() {
+    for (i, c) in code.char_indices() {
         state = match state {
 
         StateMachine::Initial => {
@@ -1216,7 +2510,9 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
             ')' => return (Some(IRTok::RParen), &code[i + 1..]),
             '+' => return (Some(IRTok::Plus), &code[i + 1..]),
             ']' => return (Some(IRTok::RBrace), &code[i + 1..]),
+            '"' => return lex_ir_string(&code[i + 1..]),
             '0'..='9' => StateMachine::Num,
+            '-' => StateMachine::Sign,
             ';' => StateMachine::Comments,
             _ => StateMachine::Ident,
             }
@@ -1229,7 +2525,7 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
             }
             if c.is_whitespace() {
                 let tok = opcode(&code[..i]);
-                return (tok, &code[i+1..]);
+                return (tok, &code[i + c.len_utf8()..]);
             }
 
             StateMachine::Lit
@@ -1242,7 +2538,7 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
             }
             if c.is_whitespace() {
                 let tok = &code[..i];
-                return (Some(IRTok::Label(String::from(tok))), &code[i+1..]);
+                return (Some(IRTok::Label(String::from(tok))), &code[i + c.len_utf8()..]);
             }
 
             StateMachine::Label
@@ -1264,21 +2560,41 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
 
             if c.is_whitespace() {
                 let tok = IRTok::Var(String::from(&code[..i]));
-                return (Some(tok), &code[i+1..]);
+                return (Some(tok), &code[i + c.len_utf8()..]);
             }
 
             StateMachine::Ident
         }
 
+        // negative number literal, otherwise an identifier starting with '-'.
+        StateMachine::Sign => {
+            if c.is_ascii_digit() {
+                StateMachine::Num
+            } else {
+                StateMachine::Ident
+            }
+        }
+
         StateMachine::Num => {
-            if c >= '0' && c <= '9' {
+            if c.is_ascii_digit() {
                 StateMachine::Num
+            } else if c == '.' {
+                StateMachine::Fraction
             } else {
                 let tok = num_literal(&code[..i]);
                 return (tok, &code[i..]);
             }
 
         }
+
+        StateMachine::Fraction => {
+            if c.is_ascii_digit() {
+                StateMachine::Fraction
+            } else {
+                let tok = float_literal(&code[..i]);
+                return (tok, &code[i..]);
+            }
+        }
         
         };
     }
@@ -1295,12 +2611,50 @@ fn lex_ir_token(mut code: &str) -> (Option<IRTok>, &str) {
     }
 
 
-    _ => {
-        println!("{:?} {}", state, code);
-        todo!()
+    // a number, label or comment needs the end of the line.
+    _ => (None, ""),
+
     }
+}
 
+// the rest of a string literal after the opening '"', using the escapes of str::escape_default.
+fn lex_ir_string(code: &str) -> (Option<IRTok>, &str) {
+    let mut text = String::new();
+    let mut chars = code.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+        '"' => return (Some(IRTok::Str(text)), &code[i + 1..]),
+        '\\' => {
+            let c = match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '0')) => '\0',
+            Some((_, c @ ('\\' | '"' | '\''))) => c,
+            // \u{e9}
+            Some((u, 'u')) => {
+                if !code[u..].starts_with("u{") {
+                    return (None, "");
+                }
+                let end = match code[u..].find('}') {
+                Some(end) if end >= 2 => u + end,
+                _ => return (None, ""),
+                };
+                let c = u32::from_str_radix(&code[u + 2..end], 16).ok().and_then(char::from_u32);
+                while chars.next().is_some_and(|(i, _)| i < end) {}
+                match c {
+                Some(c) => c,
+                None => return (None, ""),
+                }
+            }
+            _ => return (None, ""),
+            };
+            text.push(c);
+        }
+        _ => text.push(c),
+        }
     }
+    (None, "")
 }
 
 #[cfg(test)]
@@ -1312,8 +2666,16 @@ mod ir_tests {
         assert!(matches!(lex_ir_token("  %int"), (Some(IRTok::Int), _)));
         assert!(matches!(lex_ir_token(" %int[]"), (Some(IRTok::IntArray), _)));
         assert!(matches!(lex_ir_token("%call"), (Some(IRTok::Call), _)));
+        assert!(matches!(lex_ir_token("%icall"), (Some(IRTok::CallIndirect), _)));
+        assert!(matches!(lex_ir_token("%funcref"), (Some(IRTok::FuncRef), _)));
         assert!(matches!(lex_ir_token("%ret"), (Some(IRTok::Return), _)));
         assert!(matches!(lex_ir_token("%out"), (Some(IRTok::Out), _)));
+        assert!(matches!(lex_ir_token("%out_val"), (Some(IRTok::OutVal), _)));
+        assert!(matches!(lex_ir_token(r#" "x=\t\"\u{e9}\"\n" "#), (Some(IRTok::Str(text)), _) if text == "x=\t\"\u{e9}\"\n"));
+        assert!(matches!(lex_ir_token(r#""\u}x""#), (None, _)));
+        assert!(matches!(lex_ir_token(r#""\u{""#), (None, _)));
+        assert!(matches!(lex_ir_token(r#""\u{}""#), (None, _)));
+        assert!(matches!(lex_ir_token("12"), (None, _)));
         assert!(matches!(lex_ir_token("   %input"), (Some(IRTok::In), _)));
         assert!(matches!(lex_ir_token("%mov  "), (Some(IRTok::Mov), _)));
         assert!(matches!(lex_ir_token("%add"), (Some(IRTok::Add), _)));
@@ -1330,6 +2692,27 @@ mod ir_tests {
         assert!(matches!(lex_ir_token("%jmp"), (Some(IRTok::Jump), _)));
         assert!(matches!(lex_ir_token("%branch_if"), (Some(IRTok::BranchIf), _)));
         assert!(matches!(lex_ir_token("%branch_ifn"), (Some(IRTok::BranchIfNot), _)));
+        assert!(matches!(lex_ir_token("%float"), (Some(IRTok::Float), _)));
+        assert!(matches!(lex_ir_token("%float[]"), (Some(IRTok::FloatArray), _)));
+        assert!(matches!(lex_ir_token("%int[][]"), (Some(IRTok::IntHandleArray), _)));
+        assert!(matches!(lex_ir_token("%float[][]"), (Some(IRTok::FloatHandleArray), _)));
+        assert!(matches!(lex_ir_token("%new"), (Some(IRTok::New), _)));
+        assert!(matches!(lex_ir_token("%fadd"), (Some(IRTok::FAdd), _)));
+        assert!(matches!(lex_ir_token("%fpow"), (Some(IRTok::FPow), _)));
+        assert!(matches!(lex_ir_token("%gcd"), (Some(IRTok::Gcd), _)));
+        assert!(matches!(lex_ir_token("%srand"), (Some(IRTok::Srand), _)));
+        assert!(matches!(lex_ir_token("%arg"), (Some(IRTok::Arg), _)));
+        assert!(matches!(lex_ir_token("%getc"), (Some(IRTok::Getc), _)));
+        assert!(matches!(lex_ir_token("%eof"), (Some(IRTok::Eof), _)));
+        assert!(matches!(lex_ir_token("%flt"), (Some(IRTok::FLessThan), _)));
+        assert!(matches!(lex_ir_token("%itof"), (Some(IRTok::IntToFloat), _)));
+        assert!(matches!(lex_ir_token("%ftoi"), (Some(IRTok::FloatToInt), _)));
+        assert!(matches!(lex_ir_token("%bounds"), (Some(IRTok::Bounds), _)));
+        assert!(matches!(lex_ir_token("%len"), (Some(IRTok::Len), _)));
+        assert!(matches!(lex_ir_token("-40\n"), (Some(IRTok::Num(-40)), _)));
+        assert!(matches!(lex_ir_token("%assert"), (Some(IRTok::Assert), _)));
+        assert!(matches!(lex_ir_token("%exit"), (Some(IRTok::Exit), _)));
+        assert!(matches!(lex_ir_token("2.5\n"), (Some(IRTok::FloatNum(_)), "\n")));
         assert!(matches!(lex_ir_token("[are"), (Some(IRTok::LBrace), "are")));
         assert!(matches!(lex_ir_token("]are"), (Some(IRTok::RBrace), "are")));
         assert!(matches!(lex_ir_token(",are"), (Some(IRTok::Comma), "are")));
@@ -1389,11 +2772,68 @@ mod ir_tests {
             assert!(matches!(toks[8], IRTok::EndInstr));
         }
     }
+
+    // runs the body of 'main' and returns the int it returned or exited with, or the error it stopped with.
+    fn run(functions: &str, main: &str) -> Result<i32, String> {
+        let code = format!("{functions}%func main ()\n{main}\n%endfunc\n");
+        let bytecode = parse_ir(&lex_ir(&code), &mut 0).map_err(|e| e.message)?;
+        let mut runtime = Runtime {
+            input: Input::new(false),
+            random: Random::new(1),
+            args: vec![],
+            heap: None,
+        };
+        let main = bytecode.iter().find(|function| function.name == "main").unwrap();
+        match run_bytecode(&mut runtime, main, &bytecode, &[]) {
+            Ok(values) => match values[0] {
+                Value::Int(n) => Ok(n),
+                _ => Err(String::from("main did not return an int")),
+            },
+            Err(Trap::Exit(n)) => Ok(n),
+            Err(Trap::Error(e)) => Err(e.message),
+        }
+    }
+
+    fn fails(functions: &str, main: &str, message: &str) -> bool {
+        run(functions, main).is_err_and(|e| e.contains(message))
+    }
+
+    #[test]
+    fn ir_run() {
+        assert!(run("", "%int x\n%add x, 2, 3\n%ret x").is_ok_and(|n| n == 5));
+        assert!(run("", "%exit 3\n%ret 0").is_ok_and(|n| n == 3));
+
+        // arrays.
+        assert!(fails("", "%int[] a, 3\n%mov [a + 3], 1\n%ret 0", "out of bounds"));
+        assert!(fails("", "%int[] m, 2, 2\n%bounds m, 2, 2, 2\n%ret 0", "out of bounds in dimension 2"));
+        assert!(run("", "%int[] a, 4\n%int n\n%len n, a\n%ret n").is_ok_and(|n| n == 4));
+        assert!(run("", "%int[] h\n%new h, 2\n%mov [h + 1], 7\n%int x\n%mov x, [h + 1]\n%ret x").is_ok_and(|n| n == 7));
+        assert!(fails("", "%int[] h\n%new h, -1\n%ret 0", "must be positive"));
+        assert!(fails("", "%int[] h\n%int n\n%len n, h\n%ret n", "before an array was assigned"));
+
+        // int overflow and division by zero.
+        assert!(fails("", "%int x\n%pow x, 10, 10\n%ret x", "does not fit in an int"));
+        assert!(fails("", "%int x\n%gcd x, -2147483648, 0\n%ret x", "does not fit in an int"));
+        assert!(fails("", "%int x\n%abs x, -2147483648\n%ret x", "does not fit in an int"));
+        assert!(run("", "%int x\n%gcd x, -12, 18\n%ret x").is_ok_and(|n| n == 6));
+        assert!(fails("", "%int x\n%int zero\n%div x, 1, zero\n%ret x", "divide by zero"));
+
+        // calls through function references.
+        let one = "%func one (%int x)\n%ret x\n%endfunc\n";
+        assert!(run(one, "%int f\n%funcref f, one\n%int r\n%icall r, f(4)\n%ret r").is_ok_and(|n| n == 4));
+        assert!(fails(one, "%int f\n%int r\n%icall r, f(4)\n%ret r", "never assigned"));
+        assert!(fails(one, "%int f\n%mov f, -2147483648\n%int r\n%icall r, f(4)\n%ret r", "never assigned"));
+        assert!(fails(one, "%int f\n%funcref f, one\n%int r\n%icall r, f()\n%ret r", "takes 1 parameters"));
+
+        // assertions and random numbers.
+        assert!(fails("", "%int c\n%assert c, 7\n%ret 0", "at line 7"));
+        assert!(run("", "%srand 4\n%int a\n%rand a\n%srand 4\n%int b\n%rand b\n%sub a, a, b\n%ret a").is_ok_and(|n| n == 0));
+    }
 }
 
 
 #[derive(Debug)]
-enum IRTok {
+pub(crate) enum IRTok {
     // func
     Func,
     EndFunc,
@@ -1401,14 +2841,26 @@ enum IRTok {
     // declarations.
     Int,
     IntArray,
+    Float,
+    FloatArray,
+    IntHandleArray,
+    FloatHandleArray,
+    New,
 
     // function calling routines.
     Call,
+    CallIndirect,
+    FuncRef,
     Return,
 
     // input/output routines.
     Out,
+    OutStr,
+    OutVal,
     In,
+    Getc,
+    Putc,
+    Eof,
 
     // mathematical operators.
     Mov,
@@ -1417,6 +2869,29 @@ enum IRTok {
     Mult,
     Div,
     Mod,
+    FAdd,
+    FSub,
+    FMult,
+    FDiv,
+
+    // math library.
+    Abs,
+    FAbs,
+    Min,
+    FMin,
+    Max,
+    FMax,
+    Pow,
+    FPow,
+    Sqrt,
+    Gcd,
+    Rand,
+    Srand,
+    Arg,
+
+    // conversions.
+    IntToFloat,
+    FloatToInt,
 
     // comparison operators.
     LessThan,
@@ -1425,6 +2900,12 @@ enum IRTok {
     Equal,
     GreaterEqual,
     GreaterThan,
+    FLessThan,
+    FLessEqual,
+    FNotEqual,
+    FEqual,
+    FGreaterEqual,
+    FGreaterThan,
 
     // labels/branching
     Label(String),
@@ -1432,56 +2913,176 @@ enum IRTok {
     BranchIf,
     BranchIfNot,
 
+    // array bounds checking.
+    Bounds,
+    Len,
+    // stopping the program.
+    Assert,
+    Exit,
+
     Comma,
     LBrace,
     RBrace,
     LParen,
     RParen,
     Plus,
+    Str(String),
 
     EndInstr,
 
     InvalidInstruction(String),
 
     Num(i32),
+    FloatNum(f64),
     Var(String),
 }
 
 #[derive(Debug, Clone)]
 enum Op {
     Num(i32),
+    Float(f64),
     Var(i32),
 }
 
 #[derive(Debug)]
 enum VariableType {
-    IntVar(i32),
-    ArrayVar(i32, i32),
+    Int(i32),
+    Array(i32, i32),
+    Float(i32),
+    FloatArray(i32, i32),
+    // '%int[][]', an array of int arrays.
+    HandleArray(i32, i32, ValueType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueType {
+    Int,
+    Float,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Int => write!(f, "an int"),
+            ValueType::Float => write!(f, "a float"),
+        }
+    }
+}
+
+// runtime values are tagged, so a variable always knows whether it holds an int or a float.
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i32),
+    Float(f64),
+    // an element of an array of arrays, None until an array is stored in it.
+    Handle(Option<Array>),
+}
+
+impl Value {
+    // the IR is type checked before it runs, so the conversions below only
+    // matter for operands that are already of the requested type.
+    fn as_int(&self) -> i32 {
+        match self {
+        Value::Int(num) => *num,
+        Value::Float(num) => *num as i32,
+        Value::Handle(_) => unreachable!("arrays are never used as numbers"),
+        }
+    }
+
+    fn as_float(&self) -> f64 {
+        match self {
+        Value::Int(num) => *num as f64,
+        Value::Float(num) => *num,
+        Value::Handle(_) => unreachable!("arrays are never used as numbers"),
+        }
+    }
+
+    fn same_type(&self, other: &Value) -> bool {
+        matches!((self, other), (Value::Int(_), Value::Int(_)) | (Value::Float(_), Value::Float(_)) | (Value::Handle(_), Value::Handle(_)))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+        Value::Int(_) => "an int",
+        Value::Float(_) => "a float",
+        Value::Handle(_) => "an array",
+        }
+    }
+
+    // parses user input as a value of the same type as self.
+    fn parse_same_type(&self, input: &str) -> Option<Value> {
+        match self {
+        Value::Int(_) => input.parse::<i32>().ok().map(Value::Int),
+        Value::Float(_) => input.parse::<f64>().ok().map(Value::Float),
+        Value::Handle(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+        Value::Int(num) => write!(f, "{}", num),
+        // {:?} keeps the decimal point for whole numbers, i.e. '2.0' instead of '2'.
+        Value::Float(num) => write!(f, "{:?}", num),
+        Value::Handle(_) => write!(f, "array"),
+        }
+    }
+}
+
+// a '%call' argument. arrays are passed by reference.
+#[derive(Debug, Clone)]
+enum Param {
+    Value(Op),
+    Array(i32),
+}
+
+// arrays are shared between the caller and callee when passed as a parameter.
+type Array = Rc<RefCell<Vec<Value>>>;
+
+enum Argument {
+    Value(Value),
+    Array(Array),
 }
 
 #[derive(Debug)]
 enum MemWrite {
     IntVar(i32),
+    FloatVar(i32),
     ArrayWrite(i32, Op),
 }
 
+impl MemWrite {
+    // reads the value currently stored at the destination.
+    fn as_read(&self) -> MemRead {
+        match self {
+        MemWrite::IntVar(id) => MemRead::IntVar(*id),
+        MemWrite::FloatVar(id) => MemRead::FloatVar(*id),
+        MemWrite::ArrayWrite(id, index) => MemRead::ArrayRead(*id, index.clone()),
+        }
+    }
+}
+
 #[derive(Debug)]
 enum MemRead {
     IntVar(i32),
+    FloatVar(i32),
     Number(i32),
+    Float(f64),
     ArrayRead(i32, Op),
 }
 
 // TODO: array bounds check.
-fn read_memory(variables: &HashMap<i32, i32>, arrays: &HashMap<i32, Vec<i32>>, read: &MemRead) -> Result<i32, IRError> {
+fn read_memory(variables: &HashMap<i32, Value>, arrays: &HashMap<i32, Array>, read: &MemRead) -> Result<Value, IRError> {
     match read {
-    MemRead::IntVar(id) => Ok(*variables.get(&id).unwrap()),
-    MemRead::Number(number) => Ok(*number),
+    MemRead::IntVar(id) | MemRead::FloatVar(id) => Ok(variables.get(id).unwrap().clone()),
+    MemRead::Number(number) => Ok(Value::Int(*number)),
+    MemRead::Float(number) => Ok(Value::Float(*number)),
     MemRead::ArrayRead(id, index) => {
-        let array = arrays.get(&id).unwrap();
+        let array = get_array(arrays, id)?.borrow();
         let variable = read_integer_value(&variables, &index);
         if variable >= 0 && (variable as usize) < array.len() {
-            Ok(array[variable as usize])
+            Ok(array[variable as usize].clone())
         } else {
             error(MAX_LINE, format!("Runtime Error: Array out of bounds. Index {}. Array Length {}.", variable, array.len()))
         }
@@ -1489,6 +3090,24 @@ fn read_memory(variables: &HashMap<i32, i32>, arrays: &HashMap<i32, Vec<i32>>, r
     }
 }
 
+fn write_memory(variables: &mut HashMap<i32, Value>, arrays: &HashMap<i32, Array>, write: &MemWrite, value: Value) -> Result<(), IRError> {
+    match write {
+    MemWrite::IntVar(id) | MemWrite::FloatVar(id) => {
+        *variables.get_mut(id).unwrap() = value;
+        Ok(())
+    }
+    MemWrite::ArrayWrite(id, index) => {
+        let mut array = get_array(arrays, id)?.borrow_mut();
+        let index = read_integer_value(variables, index);
+        if index >= 0 && (index as usize) < array.len() {
+            array[index as usize] = value;
+            Ok(())
+        } else {
+            error(MAX_LINE, format!("Runtime Error: Array out of bounds. Value {}. Array Length {}", index, array.len()))
+        }
+    }
+    }
+}
 
 #[derive(Debug)]
 enum Bytecode {
@@ -1499,11 +3118,26 @@ enum Bytecode {
 
     // declarations.
     Int(i32),
-    IntArray(i32, i32),
+    IntArray(i32, Vec<Op>),
+    Float(i32),
+    FloatArray(i32, Vec<Op>),
+    HandleArray(i32, Vec<Op>),
+    ArrayRef(i32),
+
+    // arrays on the heap.
+    New(i32, Op, ValueType),
+    Bind(i32, i32),
+    LoadHandle(i32, MemRead),
+    StoreHandle(MemWrite, i32),
 
     // input/output routines.
     Out(Op),
-    In(i32),
+    OutStr(String),
+    OutVal(Op),
+    In(MemWrite),
+    Getc(i32),
+    Putc(Op),
+    Eof(i32),
 
     // mathematical operators.
     Mov(MemWrite, MemRead),
@@ -1512,9 +3146,35 @@ enum Bytecode {
     Mult(i32, Op, Op),
     Div(i32, Op, Op),
     Mod(i32, Op, Op),
+    FAdd(i32, Op, Op),
+    FSub(i32, Op, Op),
+    FMult(i32, Op, Op),
+    FDiv(i32, Op, Op),
+
+    // math library.
+    Abs(i32, Op),
+    FAbs(i32, Op),
+    Min(i32, Op, Op),
+    FMin(i32, Op, Op),
+    Max(i32, Op, Op),
+    FMax(i32, Op, Op),
+    Pow(i32, Op, Op),
+    FPow(i32, Op, Op),
+    Sqrt(i32, Op),
+    Gcd(i32, Op, Op),
+    Rand(i32),
+    Srand(Op),
+    Arg(i32, Op),
+
+    // conversions.
+    IntToFloat(i32, Op),
+    FloatToInt(i32, Op),
 
     // calling functions.
-    Call(i32, usize, Vec<Op>),
+    Call(Vec<i32>, usize, Vec<Param>),
+    // the function is read from a variable holding a '%funcref'.
+    CallIndirect(Vec<i32>, i32, Vec<Param>),
+    FuncRef(i32, usize),
 
     // comparison operators.
     LessThan(i32, Op, Op),
@@ -1523,10 +3183,20 @@ enum Bytecode {
     Equal(i32, Op, Op),
     GreaterEqual(i32, Op, Op),
     GreaterThan(i32, Op, Op),
-    Return(Op),
+    FLessThan(i32, Op, Op),
+    FLessEqual(i32, Op, Op),
+    FNotEqual(i32, Op, Op),
+    FEqual(i32, Op, Op),
+    FGreaterEqual(i32, Op, Op),
+    FGreaterThan(i32, Op, Op),
+    Return(Vec<Param>),
     Jmp(usize),
     BranchIf(Op, usize),
     BranchIfn(Op, usize),
+    Bounds(String, i32, Op, Op),
+    Assert(Op, usize),
+    Exit(Op),
+    Len(i32, i32),
 }
 
 
@@ -1,18 +1,53 @@
-use std::{collections::VecDeque, env, fmt::format, fs};
+use std::{collections::VecDeque, env, fmt, fs, process};
 
 mod interpreter;
 
 fn main() {
     // get commandline arguments.
-    let args: Vec<String> = env::args().collect();
+    // --seed <number> makes rand() reproducible.
+    // --strict-input makes input that isn't a number a runtime error instead of asking again.
+    // --debug-heap reports arrays created with 'new' that were never freed.
+    // numbers after the source file are passed to the program, see 'arg'.
+    // exits with 1 if the program cannot be compiled, otherwise with the status of the program.
+    let mut args: Vec<String> = env::args().collect();
+    let mut options = interpreter::RunOptions {
+        seed: None,
+        strict_input: false,
+        args: vec![],
+        debug_heap: false,
+    };
+    if let Some(i) = args.iter().position(|arg| arg == "--strict-input") {
+        options.strict_input = true;
+        args.remove(i);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--debug-heap") {
+        options.debug_heap = true;
+        args.remove(i);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--seed") {
+        options.seed = match args.get(i + 1).map(|number| number.parse::<u64>()) {
+            Some(Ok(number)) => Some(number),
+            _ => {
+                println!("--seed expects a non-negative number.");
+                process::exit(1);
+            }
+        };
+        args.drain(i..i + 2);
+    }
+
     if args.len() == 1 {
         println!("Please provide an input file.");
-        return;
+        process::exit(1);
     }
 
-    if args.len() > 2 {
-        println!("Too many commandline arguments.");
-        return;
+    for arg in &args[2..] {
+        match arg.parse::<i32>() {
+            Ok(number) => options.args.push(number),
+            Err(_) => {
+                println!("Program arguments must be integers, found '{arg}'.");
+                process::exit(1);
+            }
+        }
     }
 
     // read the entire file.
@@ -21,7 +56,7 @@ fn main() {
     let code = match result {
         Err(error) => {
             println!("**Error. File \"{}\": {}", filename, error);
-            return;
+            process::exit(1);
         }
 
         Ok(code) => {
@@ -29,14 +64,15 @@ fn main() {
         }
     };
 
-    let tokens = match lex(&code) {
+    // macros are expanded before the tokens are parsed.
+    let tokens = match lex(&code).and_then(|(tokens, lines)| expand_macros(tokens, lines)) {
         // If lex returns an Err, this block of code will execute.
         Err(error_message) => {
             println!("**Error**");
             println!("----------------------");
             println!("{}", error_message);
             println!("----------------------");
-            return;
+            process::exit(1);
         },
         
         // lex returns an Ok
@@ -52,7 +88,8 @@ fn main() {
         println!("-------------------------------");
         println!("{generated_code}");
         println!("-------------------------------");
-        interpreter::execute_ir(&generated_code);
+        let status = interpreter::execute_ir(&generated_code, &options);
+        process::exit(status);
         }
 
         Err(message) => {
@@ -64,6 +101,7 @@ fn main() {
                 println!("Error: {message}");
                 println!("----------------------");
             }
+            process::exit(1);
         }
 
     }
@@ -87,16 +125,44 @@ enum Token {
     Divide,
     Modulus,
     Assign,
+    PlusAssign,
+    SubtractAssign,
+    Increment,
+    Decrement,
+    MultiplyAssign,
+    DivideAssign,
+    ModulusAssign,
     Num(i32),
+    FloatNum(f64),
+    Str(String),
     Ident(String),
     If,
     While,
+    Do,
+    For,
+    In,
     Read, 
     Func,
     Return,
     Int,
+    Float,
+    Len,
+    New,
+    Struct,
+    Enum,
+    Const,
+    Dot,
+    DotDot,
+    Colon,
+    Question,
 
     Print,
+    // the IR inside 'asm { ... }' and the line of the '{'.
+    Asm(String, usize),
+    // the source line, reported when the assertion fails.
+    Assert(usize),
+    Macro,
+    Exit,
     Else,
     Break,
     Continue,
@@ -117,11 +183,15 @@ enum Token {
 }
 
 
-// Returns a List of Tokens or an Error String.
-fn lex(mut code: &str) -> Result<Vec<Token>, String> {
+// Returns a List of Tokens and the line each of them starts on, or an Error String.
+fn lex(mut code: &str) -> Result<(Vec<Token>, Vec<usize>), String> {
     // List of Tokens.
     let mut tokens: Vec<Token> = vec![];
+    let mut lines: Vec<usize> = vec![];
+    let mut line = 1;
     while code.len() > 0 {
+        // the tokens found in the previous pass start on the current line.
+        lines.resize(tokens.len(), line);
 
         // Number
         let (success, token, rest) = lex_number(code);
@@ -134,34 +204,79 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
         // Remove leading whitespaces.
         let (success, rest) = lex_space(code);
         if success {
+            if code.starts_with('\n') {
+                line += 1;
+            }
             code = rest;
             continue;
         }
     
+        if code.starts_with("++") {
+            code = &code[2..];
+            tokens.push(Token::Increment);
+            continue;
+        }
+
+        if code.starts_with("+=") {
+            code = &code[2..];
+            tokens.push(Token::PlusAssign);
+            continue;
+        }
+
         if code.starts_with("+") {
             code = &code[1..];
             tokens.push(Token::Plus);
             continue;
         }
     
+        if code.starts_with("--") {
+            code = &code[2..];
+            tokens.push(Token::Decrement);
+            continue;
+        }
+
+        if code.starts_with("-=") {
+            code = &code[2..];
+            tokens.push(Token::SubtractAssign);
+            continue;
+        }
+
         if code.starts_with("-") {
             code = &code[1..];
             tokens.push(Token::Subtract);
             continue;
         }
     
+        if code.starts_with("*=") {
+            code = &code[2..];
+            tokens.push(Token::MultiplyAssign);
+            continue;
+        }
+
         if code.starts_with("*") {
             code = &code[1..];
             tokens.push(Token::Multiply);
             continue;
         }
     
+        if code.starts_with("/=") {
+            code = &code[2..];
+            tokens.push(Token::DivideAssign);
+            continue;
+        }
+
         if code.starts_with("/") {
             code = &code[1..];
             tokens.push(Token::Divide);
             continue;
         }
     
+        if code.starts_with("%=") {
+            code = &code[2..];
+            tokens.push(Token::ModulusAssign);
+            continue;
+        }
+
         if code.starts_with("%") {
             code = &code[1..];
             tokens.push(Token::Modulus);
@@ -228,6 +343,30 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
             continue;
         }
 
+        if code.starts_with("..") {
+            code = &code[2..];
+            tokens.push(Token::DotDot);
+            continue;
+        }
+
+        if code.starts_with(".") {
+            code = &code[1..];
+            tokens.push(Token::Dot);
+            continue;
+        }
+
+        if code.starts_with("?") {
+            code = &code[1..];
+            tokens.push(Token::Question);
+            continue;
+        }
+
+        if code.starts_with(":") {
+            code = &code[1..];
+            tokens.push(Token::Colon);
+            continue;
+        }
+
         if code.starts_with("(") {
             code = &code[1..];
             tokens.push(Token::LeftParen);
@@ -264,6 +403,14 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
             continue;
         }
 
+        // String literal
+        if code.starts_with("\"") {
+            let (text, rest) = lex_string(code)?;
+            code = rest;
+            tokens.push(Token::Str(text));
+            continue;
+        }
+
         // Comment
         // Skip single-line comments
         if code.starts_with("#") {
@@ -271,6 +418,7 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
                 // Move past the newline character to continue lexing from the next line.
                 // This also safely handles the case where the comment is at the end of the file.
                 code = &code[newline_index + 1..];
+                line += 1;
                 continue;
             } else {
                 // If no newline is found, the rest of the string is a comment.
@@ -291,8 +439,21 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
         // Identifier
         let (success, token, rest) = lex_identifier(code);
         if success {
+            if let Token::Assert(_) = token {
+                tokens.push(Token::Assert(line));
+            } else if let Token::Asm(_, _) = token {
+                let (text, after) = lex_asm(rest)?;
+                // lines inside the block are counted from the '{'.
+                let first = line + rest[..rest.len() - rest.trim_start().len()].matches('\n').count();
+                tokens.push(Token::Asm(String::from(text), first));
+                lines.push(line);
+                line += code[..code.len() - after.len()].matches('\n').count();
+                code = after;
+                continue;
+            } else {
+                tokens.push(token);
+            }
             code = rest;
-            tokens.push(token);
             continue;
         }
 
@@ -300,8 +461,9 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
         return Err(format!("Unidentified symbol {symbol}"));
   
     }
+    lines.resize(tokens.len(), line);
   
-    return Ok(tokens);
+    Ok((tokens, lines))
   }
 
 
@@ -310,9 +472,9 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
     enum StateMachine {
       Start,
       Number,
+      Fraction,
     }
   
-    let mut success = false;
     let mut state = StateMachine::Start;
     let mut index = 0;
     for letter in code.chars() {
@@ -320,7 +482,6 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
             StateMachine::Start => {
                 if letter >= '0' && letter <= '9' {
                 state = StateMachine::Number;
-                success = true;
                 index += 1;
                 } else {
                     return (false, Token::NotToken, "");
@@ -330,27 +491,88 @@ fn lex(mut code: &str) -> Result<Vec<Token>, String> {
             StateMachine::Number => {
                 if letter >= '0' && letter <= '9' {
                 state = StateMachine::Number;
-                success = true;
                 index += 1;
-                //} else if(letter != ' ') {
-                //    return (false, Token::NotToken, "");
+                // a '.' only starts a fraction if a digit follows it.
+                } else if letter == '.' && code[index + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                    state = StateMachine::Fraction;
+                    index += 1;
                 } else {
                     let num = code[..index].parse::<i32>().unwrap();
                     return (true, Token::Num(num), &code[index..]);
                 }
             }
+
+            StateMachine::Fraction => {
+                if letter >= '0' && letter <= '9' {
+                    index += 1;
+                } else {
+                    let num = code[..index].parse::<f64>().unwrap();
+                    return (true, Token::FloatNum(num), &code[index..]);
+                }
+            }
     
         }
     }
 
-    if success == true {
-        let num: i32 = code.parse::<i32>().unwrap();
-        return (true, Token::Num(num), "");
-    } else {
-        return (false, Token::NotToken, "");
+    match state {
+        StateMachine::Number => {
+            let num: i32 = code.parse::<i32>().unwrap();
+            (true, Token::Num(num), "")
+        }
+        StateMachine::Fraction => {
+            let num: f64 = code.parse::<f64>().unwrap();
+            (true, Token::FloatNum(num), "")
+        }
+        StateMachine::Start => {
+            (false, Token::NotToken, "")
+        }
+    }
+}
+
+
+// "text" with the escapes \n, \t, \\ and \".
+fn lex_string(code: &str) -> Result<(String, &str), String> {
+    let mut text = String::new();
+    let mut chars = code.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+        '"' => return Ok((text, &code[i + 1..])),
+        '\n' => break,
+        '\\' => match chars.next() {
+            Some((_, 'n')) => text.push('\n'),
+            Some((_, 't')) => text.push('\t'),
+            Some((_, '\\')) => text.push('\\'),
+            Some((_, '"')) => text.push('"'),
+            Some((_, c)) => return Err(format!("unknown escape sequence '\\{c}' in string")),
+            None => break,
+        },
+        _ => text.push(c),
+        }
     }
+    Err(String::from("string is missing its closing '\"'"))
 }
 
+// { %add x, x, 1 } after 'asm', the text in between is IR and is not split into tokens.
+// a '}' inside an IR string literal doesn't end the block.
+fn lex_asm(code: &str) -> Result<(&str, &str), String> {
+    let code = code.trim_start();
+    if !code.starts_with('{') {
+        return Err(String::from("expected '{' after asm"));
+    }
+    let mut in_string = false;
+    let mut chars = code.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+        '"' => in_string = !in_string,
+        '\\' if in_string => {
+            chars.next();
+        }
+        '}' if !in_string => return Ok((&code[1..i], &code[i + 1..])),
+        _ => {}
+        }
+    }
+    Err(String::from("asm block is missing its closing '}'"))
+}
 
 // Removes whitespaces.
 fn lex_space(code: &str) -> (bool, &str) {
@@ -444,16 +666,29 @@ fn create_identifier(code: &str) -> Token {
         "func" => Token::Func,
         "return" => Token::Return,
         "int" => Token::Int,
+        "float" => Token::Float,
+        "len" => Token::Len,
+        "new" => Token::New,
+        "struct" => Token::Struct,
+        "enum" => Token::Enum,
+        "const" => Token::Const,
     
         // todo: implement all keywords...
         // ... all keywords...
         "print" => Token::Print,
+        "asm" => Token::Asm(String::new(), 0),
+        "macro" => Token::Macro,
+        "assert" => Token::Assert(0),
+        "exit" => Token::Exit,
         "else" => Token::Else,
         "break" => Token::Break,
         "continue" => Token::Continue,
     
         "read" => Token::Read,
         "while" => Token::While,
+        "do" => Token::Do,
+        "for" => Token::For,
+        "in" => Token::In,
         "if" => Token::If,
         _ => Token::Ident(String::from(code)),
     }
@@ -461,15 +696,379 @@ fn create_identifier(code: &str) -> Token {
 
 
 
+/*
+  ----------------------------------------------------------------
+  MACROS
+*/
+
+
+// macro swap(a, b) { ... } defined anywhere in the program.
+struct Macro {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Token>,
+    // the line of every token of the body.
+    lines: Vec<usize>,
+    // the line of 'macro'.
+    line: usize,
+}
+
+// runs between lex and parse_program: takes the macro definitions out of the tokens and replaces every use
+// 'swap(x, y)' with the body of the macro, the arguments taking the place of the parameters.
+fn expand_macros(tokens: Vec<Token>, lines: Vec<usize>) -> Result<Vec<Token>, String> {
+    let mut macros: Vec<Macro> = vec![];
+    let mut rest = vec![];
+    let mut rest_lines = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        if let Token::Macro = tokens[index] {
+            let definition = parse_macro(&tokens, &lines, &mut index)?;
+            if let Some(other) = macros.iter().find(|m| m.name == definition.name) {
+                return Err(format!("line {}: macro {} is already defined on line {}", definition.line, definition.name, other.line));
+            }
+            macros.push(definition);
+        } else {
+            rest.push(tokens[index].clone());
+            rest_lines.push(lines[index]);
+            index += 1;
+        }
+    }
+    let mut expansions = 0;
+    expand(&rest, &rest_lines, &macros, &mut vec![], &mut expansions)
+}
+
+// macro name(param, ...) { body }
+fn parse_macro(tokens: &Vec<Token>, lines: &[usize], index: &mut usize) -> Result<Macro, String> {
+    let line = lines[*index];
+    *index += 1;
+    let name = match peek(tokens, *index) {
+        Some(Token::Ident(name)) => name.clone(),
+        _ => return Err(format!("line {line}: expected the name of the macro after 'macro'")),
+    };
+    *index += 1;
+    if !matches!(peek(tokens, *index), Some(Token::LeftParen)) {
+        return Err(format!("line {line}: expected '(' after the name of macro {name}"));
+    }
+    *index += 1;
+    let mut params: Vec<String> = vec![];
+    loop {
+        match peek(tokens, *index) {
+            Some(Token::RightParen) if params.is_empty() => break,
+            Some(Token::Ident(param)) => {
+                if params.contains(param) {
+                    return Err(format!("line {line}: macro {name} has two parameters named {param}"));
+                }
+                params.push(param.clone());
+            }
+            _ => return Err(format!("line {line}: expected a parameter name in macro {name}")),
+        }
+        *index += 1;
+        match peek(tokens, *index) {
+            Some(Token::Comma) => *index += 1,
+            Some(Token::RightParen) => break,
+            _ => return Err(format!("line {line}: expected ',' or ')' after parameter {} of macro {name}", params.last().unwrap())),
+        }
+    }
+    *index += 1;
+    if !matches!(peek(tokens, *index), Some(Token::LeftCurly)) {
+        return Err(format!("line {line}: expected '{{' before the body of macro {name}"));
+    }
+    *index += 1;
+
+    let start = *index;
+    let mut depth = 0;
+    loop {
+        match peek(tokens, *index) {
+            None => return Err(format!("line {line}: the body of macro {name} is missing its closing '}}'")),
+            Some(Token::Macro) => return Err(format!("line {}: macros cannot be defined inside macro {name}", lines[*index])),
+            Some(Token::LeftCurly) => depth += 1,
+            Some(Token::RightCurly) if depth == 0 => break,
+            Some(Token::RightCurly) => depth -= 1,
+            _ => {}
+        }
+        *index += 1;
+    }
+    let body = tokens[start..*index].to_vec();
+    let body_lines = lines[start..*index].to_vec();
+    *index += 1;
+    Ok(Macro { name, params, body, lines: body_lines, line })
+}
+
+// expands the uses of macros in tokens. 'active' holds the macros currently being expanded, a macro that ends
+// up using itself would never stop expanding.
+fn expand(tokens: &[Token], lines: &[usize], macros: &Vec<Macro>, active: &mut Vec<usize>, expansions: &mut usize) -> Result<Vec<Token>, String> {
+    let mut expanded = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        let found = match &tokens[index] {
+            Token::Ident(ident) => macros.iter().position(|m| m.name == *ident),
+            _ => None,
+        };
+        let Some(id) = found else {
+            expanded.push(tokens[index].clone());
+            index += 1;
+            continue;
+        };
+        let definition = &macros[id];
+        let line = lines[index];
+        let defined = format!("(macro {} is defined on line {})", definition.name, definition.line);
+        if index > 0 && matches!(tokens[index - 1], Token::Func) {
+            return Err(format!("line {line}: function {} has the same name as a macro {defined}", definition.name));
+        }
+        if active.contains(&id) {
+            return Err(format!("line {line}: macro {} uses itself {defined}", definition.name));
+        }
+        if !matches!(tokens.get(index + 1), Some(Token::LeftParen)) {
+            return Err(format!("line {line}: expected '(' after macro {} {defined}", definition.name));
+        }
+
+        // the arguments are split at the commas that aren't nested in parentheses, brackets or braces.
+        index += 2;
+        let mut args: Vec<(usize, usize)> = vec![];
+        let mut start = index;
+        let mut depth = 0;
+        loop {
+            match tokens.get(index) {
+                None => return Err(format!("line {line}: the arguments of macro {} are missing their closing ')' {defined}", definition.name)),
+                Some(Token::LeftParen | Token::LeftBracket | Token::LeftCurly) => depth += 1,
+                Some(Token::RightParen) if depth == 0 => break,
+                Some(Token::RightParen | Token::RightBracket | Token::RightCurly) => depth -= 1,
+                Some(Token::Comma) if depth == 0 => {
+                    args.push((start, index));
+                    start = index + 1;
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        if start < index || !args.is_empty() {
+            args.push((start, index));
+        }
+        index += 1;
+        if args.len() != definition.params.len() {
+            let plural = if definition.params.len() == 1 { "" } else { "s" };
+            return Err(format!("line {line}: macro {} expects {} argument{plural}, got {} {defined}", definition.name, definition.params.len(), args.len()));
+        }
+        let mut values = vec![];
+        for (position, (start, end)) in args.into_iter().enumerate() {
+            if start == end {
+                return Err(format!("line {line}: argument {} of macro {} is empty {defined}", position + 1, definition.name));
+            }
+            // twice(3 + 1) with the body 'x * 2' is (3 + 1) * 2. an array element or a field is left as it is,
+            // so that it can still be assigned to.
+            let value = expand(&tokens[start..end], &lines[start..end], macros, active, expansions)?;
+            if value.len() > 1 && !is_element(&value) {
+                values.push([vec![Token::LeftParen], value, vec![Token::RightParen]].concat());
+            } else {
+                values.push(value);
+            }
+        }
+
+        // the variables the body declares get a name of their own for every use, which cannot be written
+        // in the source, so that they never hide or clash with the variables around the use.
+        *expansions += 1;
+        let mut locals: Vec<&String> = vec![];
+        for (i, token) in definition.body.iter().enumerate() {
+            if let Token::Ident(ident) = token {
+                let declares = i > 0 && matches!(definition.body[i - 1], Token::Int | Token::Float | Token::Func | Token::RightBracket | Token::For | Token::Ident(_));
+                if declares && !definition.params.contains(ident) && !locals.contains(&ident) {
+                    locals.push(ident);
+                }
+            }
+        }
+        let mut body = vec![];
+        let mut body_lines = vec![];
+        for (i, token) in definition.body.iter().enumerate() {
+            let field = i > 0 && matches!(definition.body[i - 1], Token::Dot);
+            match token {
+                Token::Ident(ident) if !field => match definition.params.iter().position(|param| param == ident) {
+                    // the arguments are already expanded, their tokens are only counted on the line of the use.
+                    Some(param) => {
+                        body.extend(values[param].iter().cloned());
+                        body_lines.resize(body.len(), line);
+                    }
+                    None if locals.contains(&ident) => {
+                        body.push(Token::Ident(format!("{}.{}.{ident}", definition.name, expansions)));
+                        body_lines.push(definition.lines[i]);
+                    }
+                    None => {
+                        body.push(token.clone());
+                        body_lines.push(definition.lines[i]);
+                    }
+                },
+                _ => {
+                    body.push(token.clone());
+                    body_lines.push(definition.lines[i]);
+                }
+            }
+        }
+
+        // the arguments are already expanded, so only the uses written in the body are left.
+        active.push(id);
+        let body = expand(&body, &body_lines, macros, active, expansions)
+            .map_err(|e| format!("{e}, in macro {} used on line {line}", definition.name))?;
+        active.pop();
+        // 'swap(x, y);' a body made of statements already ends with its own ';' or '}'.
+        if matches!(body.last(), Some(Token::Semicolon | Token::RightCurly)) && matches!(tokens.get(index), Some(Token::Semicolon)) {
+            index += 1;
+        }
+        expanded.extend(body);
+    }
+    Ok(expanded)
+}
+
+// values[i] or point.x, a single operand that needs no parentheses.
+fn is_element(tokens: &[Token]) -> bool {
+    if !matches!(tokens.first(), Some(Token::Ident(_))) {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(1) {
+        match token {
+            Token::LeftBracket => depth += 1,
+            Token::RightBracket => depth -= 1,
+            Token::Dot if depth == 0 => {}
+            Token::Ident(_) if depth == 0 && matches!(tokens[i - 1], Token::Dot) => {}
+            _ if depth > 0 => {}
+            _ => return false,
+        }
+    }
+    depth == 0
+}
+
+
+
 /*
   ----------------------------------------------------------------
   PARSER
 */
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Int,
+    Float,
+    // index into the struct table.
+    Struct(usize),
+    // index into the enum table, stored as an int.
+    Enum(usize),
+    // a reference to a function, stored as an int.
+    Func,
+    // a reference to an int or float array, i.e. the result of 'new int[n]'.
+    IntArray,
+    FloatArray,
+}
+
+impl Type {
+    // IR opcode used to declare a variable of this type.
+    fn declaration(&self) -> &'static str {
+        match self {
+            Type::Int | Type::Enum(_) | Type::Func => "%int",
+            Type::Float => "%float",
+            Type::IntArray => "%int[]",
+            Type::FloatArray => "%float[]",
+            Type::Struct(_) => unreachable!("structs are declared field by field"),
+        }
+    }
+
+    // the type of an array with elements of this type when it is used as a value.
+    fn array(&self) -> Type {
+        match self {
+            Type::Float => Type::FloatArray,
+            _ => Type::IntArray,
+        }
+    }
+
+    // the type of the elements, None if this isn't an array.
+    fn element(&self) -> Option<Type> {
+        match self {
+            Type::IntArray => Some(Type::Int),
+            Type::FloatArray => Some(Type::Float),
+            _ => None,
+        }
+    }
+
+    // enums are ints at runtime.
+    fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::Enum(_))
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Struct(_) => write!(f, "struct"),
+            Type::Enum(_) => write!(f, "enum"),
+            Type::Func => write!(f, "func"),
+            Type::IntArray => write!(f, "int[]"),
+            Type::FloatArray => write!(f, "float[]"),
+        }
+    }
+}
+
+// a declared variable, or an array together with the type of its elements.
+#[derive(Clone)]
+struct Variable {
+    name: String,
+    var_type: Type,
+    // length of each array dimension as an IR operand (a number or a variable), empty for scalars.
+    dims: Vec<String>,
+    // the literal of a constant, used in place of the variable.
+    value: Option<String>,
+    // '[env + i]' once the variable is captured by a nested function, see parse_nested_function.
+    // a captured array keeps its name, the nested function only gets the array it refers to.
+    env: Option<String>,
+}
+
+// struct Point { int x; int y; }
+// there is no struct type in the IR, every field is lowered to its own variable 'p.x'
+// or, for an array of structs, to its own array 'points.x'.
+struct Struct {
+    name: String,
+    fields: Vec<Variable>,
+}
+
+// enum Color { Red, Green, Blue }
+struct Enum {
+    name: String,
+    enumerators: Vec<String>,
+}
+
+// structs and enums declared at the top level of the program, shared by every function.
+struct Definitions {
+    structs: Vec<Struct>,
+    enums: Vec<Enum>,
+    constants: Vec<Variable>,
+}
+
+struct Function {
+    name: String,
+    // overloads of a name after the first get their number of parameters appended, i.e. 'f.3'.
+    ir_name: String,
+    // array parameters have a single dimension of unknown length.
+    // the value of a parameter is its default.
+    params: Vec<Variable>,
+    // func (int, float) f() returns two values.
+    return_types: Vec<Type>,
+    // arrays passed after the arguments of every call to a nested function:
+    // the environments of the variables it captures and the arrays of the enclosing function it uses.
+    hidden: Vec<Variable>,
+}
+
 struct Expression {
   code: String,
   name: String,
+  expr_type: Type,
+}
+
+// somewhere a value can be stored: a variable 'x', a struct field 'p.x' or an array element '[arr + i]'.
+// code computes the array index, if there is one.
+struct Lvalue {
+  code: String,
+  name: String,
+  lvalue_type: Type,
 }
 
 static mut VAR_NUM: i64 = 0;
@@ -481,6 +1080,9 @@ fn create_temp() -> String {
     }
 }
 
+// begin and end label of a loop, and the name it was labeled with in the source.
+type LoopLabel = (String, String, Option<String>);
+
 static mut LOOP_COUNTER: i64 = 0;
 
 fn get_unique_loop_labels() -> (String, String) {
@@ -504,6 +1106,31 @@ fn get_if_labels() -> (String, String, String) {
     }
 }
 
+// the label in front of a loop, 'outer' in outer: while ... { }
+fn loop_name(tokens: &[Token], index: usize) -> Option<String> {
+    if index < 2 || !matches!(tokens[index - 1], Token::Colon) {
+        return None;
+    }
+    match &tokens[index - 2] {
+        Token::Ident(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+// the loop targeted by 'break' or 'continue', the innermost loop unless a label follows.
+fn find_loop<'a>(tokens: &Vec<Token>, index: &mut usize, label_table: &'a VecDeque<LoopLabel>, statement: &str) -> Result<Option<&'a LoopLabel>, String> {
+    match peek_result(tokens, *index)? {
+        Token::Ident(name) => {
+            *index += 1;
+            match label_table.iter().rev().find(|(_, _, label)| label.as_ref() == Some(name)) {
+                Some(target) => Ok(Some(target)),
+                None => Err(format!("`{statement}` to unknown loop label '{name}'")),
+            }
+        }
+        _ => Ok(label_table.back()),
+    }
+}
+
 fn peek<'a>(tokens: &'a Vec<Token>, index: usize) -> Option<&'a Token> {
     if index < tokens.len() {
         return Some(&tokens[index])
@@ -547,7 +1174,7 @@ fn next_result<'a>(tokens: &'a Vec<Token>, index: &mut usize) -> Result<&'a Toke
 // print(a)
 // read(a)
 // returns epsilon if '}'
-fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, function_table: &mut Vec<String>, arr_table: &mut Vec<String>, label_table: &mut VecDeque<(String, String)>) -> Result<Option<String>, String> {
+fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions, label_table: &mut VecDeque<LoopLabel>) -> Result<Option<String>, String> {
     match peek(tokens, *index) {
     None => {
         return Ok(None);
@@ -561,71 +1188,69 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 return Ok(None);
             }
             
-            // Array or int declaration
-            Token::Int => {
-                let code = parse_declaration(tokens, index, symbol_table, arr_table)?;
+            // Array, int or float declaration
+            Token::Int | Token::Float => {
+                let code = parse_declaration(tokens, index, symbol_table, function_table, arr_table, definitions)?;
                 if !matches!(next_result(tokens, index)?, Token::Semicolon) {
                     return Err(String::from("expect ';' closing statement"));
                 }
                 codenode = Some(code);
             }
 
-            // ident = expression or arr[expression] = expression
-            Token::Ident(ident) => {
-                *index += 1;
-                let code: String;
+            Token::Func if is_nested_function(tokens, *index) => {
+                return Err(String::from("functions can only be declared at the top level of a function body"));
+            }
 
-                // Assign expression to array element 
-                if matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
-                    // %mov [array + i], src1
-                    if !find_symbol(&arr_table, ident){
-                        return Err(format!("Array '{ident}' not declared"));
-                    }
-                    *index += 1;
-                    let expr2 = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                    if !matches!(next_result(tokens, index)?, Token::RightBracket) {
-                        return Err(String::from("expected ']'"));
-                    }
-                    if !matches!(next_result(tokens, index)?, Token::Assign) {
-                        return Err(String::from("expected '=' assignment operator"));
-                    }
-                    let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                    code = format!("{}{}%mov [{} + {}], {}\n", expr.code, expr2.code, ident, expr2.name, expr.name);
-                } else {
-                    if !find_symbol(&symbol_table, ident) {
-                        return Err(format!("Variable not declared: {ident}"));
-                    }
-                    if !matches!(next_result(tokens, index)?, Token::Assign) {
-                        return Err(String::from("expected '=' assignment operator"));
-                    }
-                    let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                    code = format!("{}%mov {}, {}\n", expr.code, ident, expr.name);
-                }    
+            // func f; a variable holding a reference to a function.
+            Token::Func => {
+                *index += 1;
+                let name = match next_result(tokens, index)? {
+                    Token::Ident(name) => name,
+                    _ => return Err(String::from("expected identifier after 'func'")),
+                };
+                if find_symbol(symbol_table, name).is_some() || find_symbol(arr_table, name).is_some() {
+                    return Err(format!("Found a duplicate variable {name}"));
+                }
+                symbol_table.push(Variable { name: name.clone(), var_type: Type::Func, dims: vec![], value: None, env: None });
                 if !matches!(next_result(tokens, index)?, Token::Semicolon) {
                     return Err(String::from("expect ';' closing statement"));
                 }
-                codenode = Some(code);
+                codenode = Some(format!("%int {name}\n"));
             }
 
-            // return expression
-            Token::Return => {
-                *index += 1;
-                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                let code = format!("{}%ret {}\n", expr.code, expr.name);
-                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
-                    return Err(String::from("expect ';' closing statement"));
+            // const int N = expression;
+            Token::Const => {
+                let constant = parse_constant(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if find_symbol(symbol_table, &constant.name).is_some() {
+                    return Err(format!("Found a duplicate variable {}", constant.name));
                 }
-                codenode = Some(code);
+                symbol_table.push(constant);
+                codenode = Some(String::new());
             }
 
-            // print(expression)
-            Token::Print => {
+            // outer: while ... { break outer; }
+            Token::Ident(name) if matches!(peek(tokens, *index + 1), Some(Token::Colon)) => {
+                *index += 2;
+                if !matches!(peek_result(tokens, *index)?, Token::While | Token::Do | Token::For) {
+                    return Err(format!("label '{name}' must be followed by a loop"));
+                }
+                if label_table.iter().any(|(_, _, label)| label.as_ref() == Some(name)) {
+                    return Err(format!("label '{name}' is already used by an enclosing loop"));
+                }
+                return parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table);
+            }
+
+            // srand(expression) seeds rand(), putc(expression) writes a single byte.
+            Token::Ident(ident) if (ident == "srand" || ident == "putc") && matches!(peek(tokens, *index + 1), Some(Token::LeftParen)) && find_function(function_table, ident).is_none() => {
                 *index += 1;
                 if !matches!(next_result(tokens, index)?, Token::LeftParen) {
                     return Err(String::from("expect '(' opening statement"));
                 }
-                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                let code = format!("{}%out {}\n", expr.code, expr.name);
+                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if !expr.expr_type.is_integer() {
+                    return Err(format!("{ident} expects an int"));
+                }
+                let code = format!("{}%{ident} {}\n", expr.code, expr.name);
                 if !matches!(next_result(tokens, index)?, Token::RightParen) {
                     return Err(String::from("expect ')' closing statement"));
                 }
@@ -634,35 +1259,228 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 }
                 codenode = Some(code);
             }
-            
-            // read(expression)
-            Token::Read => {
-                *index += 1;
-                if !matches!(next_result(tokens, index)?, Token::LeftParen) {
-                    return Err(String::from("expect '(' opening statement"));
-                }
-                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                let code = format!("{}%input {}\n", expr.code, expr.name);
-                if !matches!(next_result(tokens, index)?, Token::RightParen) {
-                    return Err(String::from("expect ')' closing statement"));
+
+            // printf("x=%d, y=%f\n", x, y)
+            Token::Ident(ident) if ident == "printf" && matches!(peek(tokens, *index + 1), Some(Token::LeftParen)) && find_function(function_table, ident).is_none() => {
+                *index += 2;
+                let format = match next_result(tokens, index)? {
+                    Token::Str(format) => format,
+                    _ => return Err(String::from("printf expects a format string")),
+                };
+                let args = match next_result(tokens, index)? {
+                    Token::RightParen => vec![],
+                    Token::Comma => parse_arguments(tokens, index, symbol_table, function_table, arr_table, definitions)?,
+                    _ => return Err(String::from("expected ',' or ')' in function call")),
+                };
+                let code = printf(format, args)?;
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
                 }
+                codenode = Some(code);
+            }
+
+            // struct declaration, Point p; or Point[10] points;
+            Token::Ident(ident) if find_type(definitions, ident).is_some() => {
+                let code = parse_declaration(tokens, index, symbol_table, function_table, arr_table, definitions)?;
                 if !matches!(next_result(tokens, index)?, Token::Semicolon) {
                     return Err(String::from("expect ';' closing statement"));
                 }
                 codenode = Some(code);
             }
 
-            // ----------------------------------------------------------------
+            // ident = expression or arr[expression] = expression
+            // ident += expression or arr[expression][expression] -= expression
+            // p.x = expression or points[expression].x = expression
+            // ident++ or arr[expression]--
+            // q, r = function(expression, expression)
+            Token::Ident(ident) => {
+                let dest = parse_lvalue(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if matches!(peek_result(tokens, *index)?, Token::Comma) {
+                    let code = parse_multiple_assignment(tokens, index, symbol_table, function_table, arr_table, definitions, dest)?;
+                    if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                        return Err(String::from("expect ';' closing statement"));
+                    }
+                    return Ok(Some(code));
+                }
+                let mut code = dest.code;
+                let dest_type = dest.lvalue_type;
+                let dest = dest.name;
+
+                let operator = next_result(tokens, index)?;
+                let opcode = match operator {
+                    Token::Assign => None,
+                    Token::PlusAssign => Some("add"),
+                    Token::SubtractAssign => Some("sub"),
+                    Token::MultiplyAssign => Some("mult"),
+                    Token::DivideAssign => Some("div"),
+                    Token::ModulusAssign => Some("mod"),
+                    Token::Increment => Some("add"),
+                    Token::Decrement => Some("sub"),
+                    _ => return Err(String::from("expected '=' assignment operator")),
+                };
+                let mut expr = match operator {
+                    Token::Increment | Token::Decrement => Expression {
+                        code: String::new(),
+                        name: String::from("1"),
+                        expr_type: Type::Int,
+                    },
+                    _ => parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?,
+                };
 
-            // Parses the control flow logic (i.e. 'if' or 'while' statements)
-            // Works with nested control flow 
+                // compound assignment, read the current value first.
+                if let Some(opcode) = opcode {
+                    let temp = create_temp();
+                    let current = Expression {
+                        code: format!("{} {temp}\n%mov {temp}, {dest}\n", dest_type.declaration()),
+                        name: temp,
+                        expr_type: dest_type,
+                    };
+                    expr = binary_operation(current, expr, opcode)?;
+                }
+
+                let expr = coerce(expr, dest_type, &format!("assignment to '{ident}'"))?;
+                code += &format!("{}%mov {}, {}\n", expr.code, dest, expr.name);
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+
+            // return expression or return expression, expression
+            Token::Return => {
+                *index += 1;
+                // the function being parsed is always the last one in the function table.
+                let return_types = function_table.last().unwrap().return_types.clone();
+                let mut code = String::new();
+                let mut values = vec![];
+                loop {
+                    let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                    let return_type = match return_types.get(values.len()) {
+                        Some(return_type) => *return_type,
+                        None => return Err(format!("return gives more than the {} values the function returns", return_types.len())),
+                    };
+                    let expr = coerce(expr, return_type, &String::from("return value"))?;
+                    code += &expr.code;
+                    values.push(expr.name);
+                    if !matches!(peek_result(tokens, *index)?, Token::Comma) {
+                        break;
+                    }
+                    *index += 1;
+                }
+                if values.len() != return_types.len() {
+                    return Err(format!("return gives {} values, but the function returns {}", values.len(), return_types.len()));
+                }
+                code += &format!("%ret {}\n", values.join(", "));
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+
+            // print(expression)
+            Token::Print => {
+                *index += 1;
+                if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                    return Err(String::from("expect '(' opening statement"));
+                }
+                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if expr.expr_type.element().is_some() {
+                    return Err(String::from("cannot print an array, print its elements instead"));
+                }
+                let code = format!("{}%out {}\n", expr.code, expr.name);
+                if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                    return Err(String::from("expect ')' closing statement"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+            
+            // asm { %add x, x, 1 }
+            Token::Asm(text, line) => {
+                *index += 1;
+                codenode = Some(parse_asm(text, *line, symbol_table, function_table, arr_table, definitions)?);
+            }
+
+            // assert(expression) stops the program when the expression is 0.
+            Token::Assert(line) => {
+                *index += 1;
+                if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                    return Err(String::from("expect '(' opening statement"));
+                }
+                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if !expr.expr_type.is_integer() {
+                    return Err(String::from("assert expects an int condition"));
+                }
+                let code = format!("{}%assert {}, {}\n", expr.code, expr.name, line);
+                if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                    return Err(String::from("expect ')' closing statement"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+
+            // exit(expression) ends the program with the expression as its exit code.
+            Token::Exit => {
+                *index += 1;
+                if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                    return Err(String::from("expect '(' opening statement"));
+                }
+                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if !expr.expr_type.is_integer() {
+                    return Err(String::from("exit code must be an int"));
+                }
+                let code = format!("{}%exit {}\n", expr.code, expr.name);
+                if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                    return Err(String::from("expect ')' closing statement"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+
+            // read(ident), read(arr[expression]) or read(p.x)
+            Token::Read => {
+                *index += 1;
+                if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                    return Err(String::from("expect '(' opening statement"));
+                }
+                if !matches!(peek_result(tokens, *index)?, Token::Ident(_)) || matches!(peek(tokens, *index + 1), Some(Token::LeftParen)) {
+                    return Err(String::from("read expects a variable, array element or struct field"));
+                }
+                let dest = parse_lvalue(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if dest.lvalue_type == Type::Func {
+                    return Err(String::from("cannot read a function reference"));
+                }
+                if dest.lvalue_type.element().is_some() {
+                    return Err(String::from("cannot read an array, read its elements instead"));
+                }
+                let code = format!("{}%input {}\n", dest.code, dest.name);
+                if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                    return Err(String::from("read expects a variable, array element or struct field, not an expression"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' closing statement"));
+                }
+                codenode = Some(code);
+            }
+
+            // ----------------------------------------------------------------
+
+            // Parses the control flow logic (i.e. 'if' or 'while' statements)
+            // Works with nested control flow 
 
             Token::While => {
                 // while term [bool operator] term { statement* }
                 *index += 1;
+                let name = loop_name(tokens, *index - 1);
                 let (iftrue, end_label) = get_unique_loop_labels();
-                label_table.push_back((iftrue.clone(), end_label.clone()));
-                let expr = parse_bool_operation(tokens, index, symbol_table, function_table, arr_table)?;
+                label_table.push_back((iftrue.clone(), end_label.clone(), name));
+                let expr = parse_bool_operation(tokens, index, symbol_table, function_table, arr_table, definitions)?;
                 let mut code = format!("{}\n", iftrue);
                 code += &expr.code;
                 code += &format!("%branch_ifn {}, {}\n", expr.name, end_label);
@@ -672,7 +1490,7 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
 
                 // Loop statements
                 loop {
-                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, label_table)? {
+                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table)? {
                         None => {
                             break;
                         }
@@ -692,6 +1510,204 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 label_table.pop_back();
                 codenode = Some(code);
             }
+            Token::For => {
+                /*
+                    %int index
+                    %mov index, start
+                    :beginloop1
+                    %lt temp, index, end
+                    %branch_ifn temp, :endloop1
+                    %mov x, [array + index]
+                    # statements
+                    :beginloop1_increment
+                    %add index, index, 1
+                    %jmp :beginloop1
+                    :endloop1
+                */
+                // for x in array { statement* }  or  for i in start..end { statement* }
+                *index += 1;
+                let name = loop_name(tokens, *index - 1);
+                let variable = match next_result(tokens, index)? {
+                    Token::Ident(variable) => variable.clone(),
+                    _ => return Err(String::from("expected loop variable after 'for'")),
+                };
+                if !matches!(next_result(tokens, index)?, Token::In) {
+                    return Err(String::from("expected 'in' after the loop variable"));
+                }
+
+                // the array is only known if it's followed by the loop body.
+                let array = match (peek_result(tokens, *index)?, peek(tokens, *index + 1)) {
+                    (Token::Ident(ident), Some(Token::LeftCurly)) => match find_symbol(arr_table, ident) {
+                        Some(array) => Some(array.clone()),
+                        None => return Err(format!("Array {ident} undeclared")),
+                    },
+                    _ => None,
+                };
+
+                let counter = create_temp();
+                let mut code = format!("%int {counter}\n");
+                let (element_type, end) = match &array {
+                    Some(array) => {
+                        *index += 1;
+                        if array.dims.len() != 1 {
+                            return Err(format!("only one-dimensional arrays can be iterated, '{}' has {} dimensions", array.name, array.dims.len()));
+                        }
+                        if array.var_type.element().is_some() {
+                            return Err(format!("arrays of arrays can't be iterated, index '{}' instead", array.name));
+                        }
+                        if let Type::Struct(_) = array.var_type {
+                            return Err(format!("arrays of structs can't be iterated, index '{}' instead", array.name));
+                        }
+                        code += &format!("%mov {counter}, 0\n");
+                        (array.var_type, array_length(array, definitions))
+                    }
+                    None => {
+                        let start = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                        if !matches!(next_result(tokens, index)?, Token::DotDot) {
+                            return Err(String::from("expected an array or a range 'start..end' after 'in'"));
+                        }
+                        let end = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                        if !start.expr_type.is_integer() || !end.expr_type.is_integer() {
+                            return Err(String::from("range bounds must be ints"));
+                        }
+                        code += &start.code;
+                        code += &format!("%mov {counter}, {}\n", start.name);
+                        (Type::Int, end)
+                    }
+                };
+                // the end is evaluated once, before the first iteration.
+                let end = if end.code.is_empty() {
+                    end
+                } else {
+                    let temp = create_temp();
+                    code += &end.code;
+                    code += &format!("%int {temp}\n%mov {temp}, {}\n", end.name);
+                    Expression { code: String::new(), name: temp, expr_type: Type::Int }
+                };
+
+                // the loop variable is declared by the loop unless it already exists.
+                let variable_type = match find_symbol(symbol_table, &variable) {
+                    Some(existing) => {
+                        if existing.value.is_some() {
+                            return Err(format!("cannot assign to constant {variable}"));
+                        }
+                        if existing.env.is_some() {
+                            return Err(format!("loop variable {variable} is captured by a nested function"));
+                        }
+                        existing.var_type
+                    }
+                    None => {
+                        if find_symbol(arr_table, &variable).is_some() {
+                            return Err(format!("loop variable {variable} is already declared as an array"));
+                        }
+                        symbol_table.push(Variable { name: variable.clone(), var_type: element_type, dims: vec![], value: None, env: None });
+                        code += &format!("{} {}\n", element_type.declaration(), variable);
+                        element_type
+                    }
+                };
+
+                let (iftrue, end_label) = get_unique_loop_labels();
+                // continue jumps to the increment, not back to the condition.
+                let increment = format!("{iftrue}_increment");
+                label_table.push_back((increment.clone(), end_label.clone(), name));
+                let condition = create_temp();
+                code += &format!("{}\n", iftrue);
+                code += &format!("%int {condition}\n%lt {condition}, {counter}, {}\n", end.name);
+                code += &format!("%branch_ifn {}, {}\n", condition, end_label);
+                let element = match &array {
+                    Some(array) => {
+                        let temp = create_temp();
+                        Expression {
+                            code: format!("{} {temp}\n%mov {temp}, [{} + {counter}]\n", element_type.declaration(), array.name),
+                            name: temp,
+                            expr_type: element_type,
+                        }
+                    }
+                    None => Expression { code: String::new(), name: counter.clone(), expr_type: Type::Int },
+                };
+                let element = coerce(element, variable_type, &format!("loop variable '{variable}'"))?;
+                code += &element.code;
+                code += &format!("%mov {}, {}\n", variable, element.name);
+
+                if !matches!(next_result(tokens, index)?, Token::LeftCurly) {
+                    return Err(String::from("expected '{'"));
+                }
+
+                // Loop statements
+                loop {
+                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table)? {
+                        None => {
+                            break;
+                        }
+                        Some(statements) => {
+                            code += &statements;
+                        }
+                    }
+                }
+
+                if !matches!(next_result(tokens, index)?, Token::RightCurly) {
+                    return Err(String::from("expected '}' after for"));
+                }
+
+                code += &format!("{}\n", increment);
+                code += &format!("%add {counter}, {counter}, 1\n");
+                code += &format!("%jmp {}\n", iftrue);
+                code += &format!("{}\n", end_label);
+
+                label_table.pop_back();
+                codenode = Some(code);
+            }
+            Token::Do => {
+                /*
+                    :beginloop1
+                    # statements
+                    :beginloop1_condition
+                    %branch_if bool_expr, :beginloop1
+                    :endloop1
+                */
+                // do { statement* } while term [bool operator] term;
+                *index += 1;
+                let name = loop_name(tokens, *index - 1);
+                let (iftrue, end_label) = get_unique_loop_labels();
+                // continue jumps to the condition, not back to the start of the body.
+                let condition = format!("{iftrue}_condition");
+                label_table.push_back((condition.clone(), end_label.clone(), name));
+                let mut code = format!("{}\n", iftrue);
+                if !matches!(next_result(tokens, index)?, Token::LeftCurly) {
+                    return Err(String::from("expected '{'"));
+                }
+
+                // Loop statements
+                loop {
+                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table)? {
+                        None => {
+                            break;
+                        }
+                        Some(statements) => {
+                            code += &statements;
+                        }
+                    }
+                }
+
+                if !matches!(next_result(tokens, index)?, Token::RightCurly) {
+                    return Err(String::from("expected '}' after do"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::While) {
+                    return Err(String::from("expected 'while' after do loop"));
+                }
+
+                let expr = parse_bool_operation(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                code += &format!("{}\n", condition);
+                code += &expr.code;
+                code += &format!("%branch_if {}, {}\n", expr.name, iftrue);
+                code += &format!("{}\n", end_label);
+                if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+                    return Err(String::from("expect ';' after do loop"));
+                }
+
+                label_table.pop_back();
+                codenode = Some(code);
+            }
             Token::If => {
                 /*
                     %branch_if bool_expr, :iftrue1
@@ -709,7 +1725,7 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 let (iftrue, elselabel, endif) = get_if_labels();
                 let temp = create_temp();
                 let mut code = format!("%int {}\n", temp);
-                let bool_expr = parse_bool_operation(tokens, index, symbol_table, function_table, arr_table)?;
+                let bool_expr = parse_bool_operation(tokens, index, symbol_table, function_table, arr_table, definitions)?;
                 code += &bool_expr.code;
                 code += &format!("%branch_if {}, {}\n", bool_expr.name, iftrue);
                 code += &format!("%jmp {}\n", elselabel);
@@ -721,7 +1737,7 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
 
                 // Loop statements
                 loop {
-                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, label_table)? {
+                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table)? {
                         None => {
                             break;
                         }
@@ -753,7 +1769,7 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
 
                                 // looop statements
                                 loop {
-                                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, label_table)? {
+                                    match parse_statement(tokens, index, symbol_table, function_table, arr_table, definitions, label_table)? {
                                         None => {
                                             break;
                                         }
@@ -776,10 +1792,10 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 codenode = Some(code);
             }
 
-            // Break statement
+            // Break statement, break; or break outer;
             Token::Break => {
                 *index += 1;
-                if let Some((_, end_label)) = label_table.back() {
+                if let Some((_, end_label, _)) = find_loop(tokens, index, label_table, "break")? {
                     let code = format!("%jmp {}\n", end_label);
                     codenode = Some(code);
                 } else {
@@ -790,10 +1806,10 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
                 }
             }
 
-            // Continue statement
+            // Continue statement, continue; or continue outer;
             Token::Continue => {
                 *index += 1;
-                if let Some((begin_label, _)) = label_table.back() {
+                if let Some((begin_label, _, _)) = find_loop(tokens, index, label_table, "continue")? {
                     let code = format!("%jmp {}\n", begin_label);
                     codenode = Some(code);
                 } else {
@@ -815,33 +1831,106 @@ fn parse_statement(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Ve
     }
 }
 
-fn parse_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, function_table: &mut Vec<String>, arr_table: &mut Vec<String>) -> Result<Expression, String> {
-    let mut e = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table)?;
+// a comparison or a conditional expression, c ? a : b
+// only the selected arm is evaluated.
+fn parse_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Expression, String> {
+    let mut condition = parse_add_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+    // comparisons produce 0 or 1.
+    let opcode = match peek_result(tokens, *index)? {
+        Token::Equality => Some("eq"),
+        Token::NotEqual => Some("neq"),
+        Token::Greater => Some("gt"),
+        Token::GreaterEqual => Some("ge"),
+        Token::Less => Some("lt"),
+        Token::LessEqual => Some("le"),
+        _ => None,
+    };
+    if let Some(opcode) = opcode {
+        *index += 1;
+        let e2 = parse_add_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+        condition = binary_operation(condition, e2, opcode)?;
+    }
+
+    if !matches!(peek_result(tokens, *index)?, Token::Question) {
+        return Ok(condition);
+    }
+    *index += 1;
+    if !condition.expr_type.is_integer() {
+        return Err(String::from("condition of '?' must be an int"));
+    }
+    // %branch_if only accepts 0 or 1.
+    if opcode.is_none() {
+        let zero = Expression { code: String::new(), name: String::from("0"), expr_type: Type::Int };
+        condition = binary_operation(condition, zero, "neq")?;
+    }
+
+    let e = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+    if !matches!(next_result(tokens, index)?, Token::Colon) {
+        return Err(String::from("expected ':' in conditional expression"));
+    }
+    let e2 = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+    let result_type = if e.expr_type == Type::Func || e2.expr_type == Type::Func {
+        if e.expr_type != e2.expr_type {
+            return Err(String::from("both arms of a conditional expression must be function references"));
+        }
+        Type::Func
+    } else if e.expr_type.element().is_some() || e2.expr_type.element().is_some() {
+        if e.expr_type != e2.expr_type {
+            return Err(String::from("both arms of a conditional expression must be arrays of the same type"));
+        }
+        e.expr_type
+    } else if e.expr_type == Type::Float || e2.expr_type == Type::Float {
+        Type::Float
+    } else {
+        Type::Int
+    };
+    let e = convert(e, result_type);
+    let e2 = convert(e2, result_type);
+
+    /*
+        %branch_if condition, :iftrue1
+        %jmp :else1
+        :iftrue1
+        %mov temp, a
+        %jmp :endif1
+        :else1
+        %mov temp, b
+        :endif1
+    */
+    let (iftrue, elselabel, endif) = get_if_labels();
+    let temp = create_temp();
+    let mut code = condition.code;
+    code += &format!("{} {temp}\n", result_type.declaration());
+    code += &format!("%branch_if {}, {}\n", condition.name, iftrue);
+    code += &format!("%jmp {}\n", elselabel);
+    code += &format!("{}\n", iftrue);
+    code += &e.code;
+    code += &format!("%mov {temp}, {}\n", e.name);
+    code += &format!("%jmp {}\n", endif);
+    code += &format!("{}\n", elselabel);
+    code += &e2.code;
+    code += &format!("%mov {temp}, {}\n", e2.name);
+    code += &format!("{}\n", endif);
+    Ok(Expression {
+        code,
+        name: temp,
+        expr_type: result_type,
+    })
+}
+
+fn parse_add_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Expression, String> {
+    let mut e = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
     loop {
         match peek_result(tokens, *index)? {
             Token::Plus => {
                 *index += 1;
-                let e2 = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table)?;
-
-                let temp = create_temp();
-                let src1 = e.name;
-                let src2 = e2.name;
-                e.code += &format!("%int {temp}\n");
-                e.code += &e2.code;
-                e.code += &format!("%add {temp}, {src1}, {src2}\n");
-                e.name = temp;
+                let e2 = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                e = binary_operation(e, e2, "add")?;
             }
             Token::Subtract => {
                 *index += 1;
-                let e2 = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table)?;
-
-                let temp = create_temp();
-                let src1 = e.name;
-                let src2 = e2.name;
-                e.code += &format!("%int {temp}\n");
-                e.code += &e2.code;
-                e.code += &format!("%sub {temp}, {src1}, {src2}\n");
-                e.name = temp;
+                let e2 = parse_mul_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                e = binary_operation(e, e2, "sub")?;
             }
             _ => {
                 break;
@@ -852,46 +1941,25 @@ fn parse_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut V
     return Ok(e);
 }
 
-fn parse_mul_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, function_table: &mut Vec<String>, arr_table: &mut Vec<String>) -> Result<Expression, String> {
-    let mut e = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
+fn parse_mul_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Expression, String> {
+    let mut e = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
 
     loop {
         match peek_result(tokens, *index)? {
             Token::Multiply => {
                 *index += 1;
-                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
-
-                let temp = create_temp();
-                let src1 = e.name;
-                let src2 = e2.name;
-                e.code += &format!("%int {temp}\n");
-                e.code += &e2.code;
-                e.code += &format!("%mult {temp}, {src1}, {src2}\n");
-                e.name = temp;
+                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                e = binary_operation(e, e2, "mult")?;
             }
             Token::Modulus => {
                 *index += 1;
-                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
-
-                let temp = create_temp();
-                let src1 = e.name;
-                let src2 = e2.name;
-                e.code += &format!("%int {temp}\n");
-                e.code += &e2.code;
-                e.code += &format!("%mod {temp}, {src1}, {src2}\n");
-                e.name = temp;
+                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                e = binary_operation(e, e2, "mod")?;
             }
             Token::Divide => {
                 *index += 1;
-                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
-
-                let temp = create_temp();
-                let src1 = e.name;
-                let src2 = e2.name;
-                e.code += &format!("%int {temp}\n");
-                e.code += &e2.code;
-                e.code += &format!("%div {temp}, {src1}, {src2}\n");
-                e.name = temp;
+                let e2 = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                e = binary_operation(e, e2, "div")?;
             }
             _ => {
                 break;
@@ -902,82 +1970,266 @@ fn parse_mul_expression(tokens: &Vec<Token>, index: &mut usize, symbol_table: &m
     Ok(e)
 }
 
+// %add temp, src1, src2
+// if either operand is a float, both are converted to float and the float opcode (%fadd) is used.
+// comparisons (%lt, %eq, ...) always produce an int.
+fn binary_operation(e: Expression, e2: Expression, opcode: &str) -> Result<Expression, String> {
+    if e.expr_type == Type::Func || e2.expr_type == Type::Func {
+        return Err(String::from("function references can only be called, assigned or passed"));
+    }
+    if e.expr_type.element().is_some() || e2.expr_type.element().is_some() {
+        return Err(String::from("arrays can only be indexed, assigned, passed or returned"));
+    }
+    let operand_type = if e.expr_type == Type::Float || e2.expr_type == Type::Float {
+        Type::Float
+    } else {
+        Type::Int
+    };
+    let result_type = match opcode {
+        "lt" | "le" | "eq" | "neq" | "gt" | "ge" => Type::Int,
+        _ => operand_type,
+    };
+    let instruction = match operand_type {
+        Type::Int => format!("%{opcode}"),
+        Type::Float => {
+            if opcode == "mod" {
+                return Err(String::from("operator '%' requires int operands"));
+            }
+            format!("%f{opcode}")
+        }
+        _ => unreachable!("operands are converted to int or float"),
+    };
+
+    let e = convert(e, operand_type);
+    let e2 = convert(e2, operand_type);
+    if let Some(value) = fold_constants(&e, &e2, opcode) {
+        return Ok(Expression {
+            code: String::new(),
+            name: value,
+            expr_type: result_type,
+        });
+    }
+    let temp = create_temp();
+    let mut code = e.code;
+    code += &e2.code;
+    code += &format!("{} {temp}\n", result_type.declaration());
+    code += &format!("{instruction} {temp}, {}, {}\n", e.name, e2.name);
+    Ok(Expression {
+        code,
+        name: temp,
+        expr_type: result_type,
+    })
+}
+
+// evaluates an operation on two literals at compile time.
+// operations that would fail at runtime, like a division by zero, are left to the interpreter.
+fn fold_constants(e: &Expression, e2: &Expression, opcode: &str) -> Option<String> {
+    if !e.code.is_empty() || !e2.code.is_empty() {
+        return None;
+    }
+    if e.expr_type == Type::Float {
+        let a = e.name.parse::<f64>().ok()?;
+        let b = e2.name.parse::<f64>().ok()?;
+        let result = match opcode {
+            "add" => a + b,
+            "sub" => a - b,
+            "mult" => a * b,
+            "div" if b != 0.0 => a / b,
+            "min" => a.min(b),
+            "max" => a.max(b),
+            "pow" => a.powf(b),
+            "lt" => return Some(format!("{}", (a < b) as i32)),
+            "le" => return Some(format!("{}", (a <= b) as i32)),
+            "eq" => return Some(format!("{}", (a == b) as i32)),
+            "neq" => return Some(format!("{}", (a != b) as i32)),
+            "gt" => return Some(format!("{}", (a > b) as i32)),
+            "ge" => return Some(format!("{}", (a >= b) as i32)),
+            _ => return None,
+        };
+        return float_literal(result);
+    }
+
+    let a = e.name.parse::<i32>().ok()?;
+    let b = e2.name.parse::<i32>().ok()?;
+    let result = match opcode {
+        "add" => a.checked_add(b)?,
+        "sub" => a.checked_sub(b)?,
+        "mult" => a.checked_mul(b)?,
+        "div" => a.checked_div(b)?,
+        "mod" => a.checked_rem(b)?,
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "pow" => a.checked_pow(u32::try_from(b).ok()?)?,
+        "lt" => (a < b) as i32,
+        "le" => (a <= b) as i32,
+        "eq" => (a == b) as i32,
+        "neq" => (a != b) as i32,
+        "gt" => (a > b) as i32,
+        "ge" => (a >= b) as i32,
+        _ => return None,
+    };
+    Some(format!("{result}"))
+}
+
+// {:?} always keeps the decimal point, but very large or small floats use an exponent the IR can't read.
+fn float_literal(num: f64) -> Option<String> {
+    let literal = format!("{:?}", num);
+    if num.is_finite() && !literal.contains('e') {
+        Some(literal)
+    } else {
+        None
+    }
+}
+
+// explicit conversion, used by int(x) and float(x).
+// %itof temp, src  or  %ftoi temp, src
+fn convert(expr: Expression, to: Type) -> Expression {
+    if expr.expr_type == to {
+        return expr;
+    }
+    if expr.expr_type.is_integer() && to.is_integer() {
+        return Expression {
+            expr_type: to,
+            ..expr
+        };
+    }
+    // literals are converted at compile time.
+    if expr.code.is_empty() {
+        let literal = match to {
+            Type::Float => expr.name.parse::<i32>().ok().and_then(|num| float_literal(num as f64)),
+            _ => expr.name.parse::<f64>().ok().map(|num| format!("{}", num as i32)),
+        };
+        if let Some(literal) = literal {
+            return Expression {
+                code: String::new(),
+                name: literal,
+                expr_type: to,
+            };
+        }
+    }
+    let temp = create_temp();
+    let mut code = expr.code;
+    code += &format!("{} {temp}\n", to.declaration());
+    match to {
+        Type::Float => code += &format!("%itof {temp}, {}\n", expr.name),
+        Type::Int | Type::Enum(_) => code += &format!("%ftoi {temp}, {}\n", expr.name),
+        Type::Struct(_) | Type::Func | Type::IntArray | Type::FloatArray => unreachable!("structs, function references and arrays are never converted"),
+    }
+    Expression {
+        code,
+        name: temp,
+        expr_type: to,
+    }
+}
+
+// implicit conversion. ints are widened to floats, but floats are never silently truncated.
+fn coerce(expr: Expression, to: Type, context: &String) -> Result<Expression, String> {
+    // function references and arrays only match their own type.
+    let exact = |t: Type| t == Type::Func || t.element().is_some();
+    if (exact(expr.expr_type) || exact(to)) && expr.expr_type != to {
+        return Err(format!("type mismatch in {context}: expected {to}, got {}", expr.expr_type));
+    }
+    if expr.expr_type == Type::Float && to.is_integer() {
+        return Err(format!("type mismatch in {context}: expected {to}, got float. use 'int(...)' to convert"));
+    }
+    if let (Type::Enum(from), Type::Enum(id)) = (expr.expr_type, to) {
+        if from != id {
+            return Err(format!("type mismatch in {context}: enumerators of different enums cannot be mixed"));
+        }
+    }
+    Ok(convert(expr, to))
+}
+
 // a term is either a Number, an Identifier, or an array element (arr[expression])
-fn parse_term(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, function_table: &mut Vec<String>, arr_table: &mut Vec<String>) -> Result<Expression, String> {
-    let mut expr: Expression;
+fn parse_term(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Expression, String> {
+    let expr: Expression;
     match next_result(tokens, index)? {
         Token::Ident(ident) => {
-            // Function call
-            if matches!(peek_result(tokens, *index)?, Token::LeftParen) {
+            // call through a function reference, f(a, b)
+            if matches!(peek_result(tokens, *index)?, Token::LeftParen) && find_symbol(symbol_table, ident).is_some_and(|variable| variable.var_type == Type::Func) {
                 *index += 1;
-
-                if !find_symbol(function_table, ident){
-                    return Err(format!("Function {ident} not initialised"));
-                }
-
-                // %call dest, func(a,b)
-                let mut args_code = String::new();
-                let mut args = Vec::new();
-
-                // Parse all arguments
-                loop {
-                    if matches!(peek_result(tokens, *index)?, Token::RightParen) {
-                        *index += 1; // Consume ')'
-                        break;
+                let reference = read_variable(find_symbol(symbol_table, ident).unwrap());
+                // the function is only known at runtime, so the arguments are passed as they are
+                // and the number of arguments is checked when it is called.
+                let args = parse_arguments(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                let mut code = reference.code;
+                let mut names = vec![];
+                for arg in args {
+                    if arg.expr_type == Type::Func {
+                        return Err(format!("function references cannot be passed through the function reference {ident}"));
                     }
-                    let arg_expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                    args_code += &arg_expr.code;
-                    args.push(arg_expr.name);
-
-                    if matches!(peek_result(tokens, *index)?, Token::Comma) {
-                        *index += 1; // Consume ','
-                    } else if matches!(peek_result(tokens, *index)?, Token::RightParen) {
-                        *index += 1; // Consume ')'
-                        break;
-                    } else {
-                        return Err(String::from("expected ',' or ')' in function call"));
+                    if arg.expr_type.element().is_some() {
+                        return Err(format!("arrays cannot be passed through the function reference {ident}"));
                     }
+                    if arg.expr_type == Type::Float {
+                        return Err(format!("function references only take int arguments, found a float argument to {ident}"));
+                    }
+                    code += &arg.code;
+                    names.push(arg.name);
                 }
-
                 let temp = create_temp();
-                let mut call_code = format!("%int {}\n", temp);
-                call_code += &format!("%call {}, {}(", temp, ident);
-                for (i, arg) in args.iter().enumerate() {
-                    if i > 0 {
-                        call_code += ", ";
+                code += &format!("%int {temp}\n%icall {temp}, {}({})\n", reference.name, names.join(", "));
+                expr = Expression {
+                    code,
+                    name: temp,
+                    expr_type: Type::Int,
+                };
+            } else if matches!(peek_result(tokens, *index)?, Token::LeftParen) { // Function call
+                *index += 1;
+
+                // user functions take precedence over the math library.
+                if find_function(function_table, ident).is_none() {
+                    if let Some(arity) = intrinsic_arity(ident) {
+                        let args = parse_arguments(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                        if args.len() != arity {
+                            return Err(format!("wrong number of arguments passed to function {ident}, expected {arity}"));
+                        }
+                        return intrinsic(ident, args);
                     }
-                    call_code += arg;
                 }
-                call_code += &format!(")\n");
+
+                let (args_code, call, return_types) = parse_call(tokens, index, symbol_table, function_table, arr_table, definitions, ident)?;
+                if return_types.len() != 1 {
+                    return Err(format!("function {ident} returns {} values, assign them with 'a, b = {ident}(...)'", return_types.len()));
+                }
+                let return_type = return_types[0];
+
+                let temp = create_temp();
+                let mut call_code = format!("{} {}\n", return_type.declaration(), temp);
+                call_code += &format!("%call {}, {}\n", temp, call);
 
                 expr = Expression {
                     code: args_code + &call_code,
                     name: temp,
+                    expr_type: return_type,
                 };
             } else if matches!(peek_result(tokens, *index)?, Token::LeftBracket) { // Array element
-                *index += 1;
-                if !find_symbol(&arr_table, ident){
-                    return Err(format!("Array {ident} undeclared"));
-                }
-                let expr2 = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
-                if !matches!(next_result(tokens, index)?, Token::RightBracket) {
-                    return Err(String::from("expected ']'"));
-                }
+                let array = match find_symbol(arr_table, ident) {
+                    Some(array) => array.clone(),
+                    None => return Err(format!("Array {ident} undeclared")),
+                };
+                let element = parse_element(tokens, index, symbol_table, function_table, arr_table, definitions, &array)?;
+                let elem_type = element.lvalue_type;
                 let temp = create_temp();
+                // %mov temp, [array + i]
                 expr = Expression {
-                    code : format!("%int {}\n", temp),
+                    code : format!("{}{} {}\n%mov {}, {}\n", element.code, elem_type.declaration(), temp, temp, element.name),
                     name : format!("{}", temp),
+                    expr_type : elem_type,
                 };
-                // %mov [array + i], src1
-                expr.code += &format!("{}%mov {}, [{} + {}]\n", expr2.code, temp, ident, expr2.name);
             } else {
-                if !find_symbol(&symbol_table, ident) {
-                    return Err(format!("Error. undeclared variable {ident}"));
-                }
-                expr = Expression {
-                    code : String::from(""),
-                    name : ident.clone(),
+                let variable = match find_symbol(symbol_table, ident).or_else(|| find_symbol(&definitions.constants, ident)) {
+                    Some(variable) => parse_field(tokens, index, &definitions.structs, variable)?,
+                    None => match find_enumerator(definitions, ident) {
+                        // enumerators are replaced by their value.
+                        Some((id, value)) => Variable { name: ident.clone(), var_type: Type::Enum(id), dims: vec![], value: Some(format!("{value}")), env: None },
+                        None => match find_symbol(arr_table, ident) {
+                            Some(array) => return array_value(array),
+                            None => return function_reference(function_table, ident),
+                        },
+                    },
                 };
+                expr = read_variable(&variable);
             }
             return Ok(expr);
         }
@@ -985,11 +2237,106 @@ fn parse_term(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Str
             expr = Expression {
                 code : String::from(""),
                 name : format!("{}", num),
+                expr_type : Type::Int,
+            };
+            return Ok(expr);
+        }
+        Token::FloatNum(num) => {
+            let name = match float_literal(*num) {
+                Some(name) => name,
+                None => return Err(format!("float literal {num} is out of range")),
+            };
+            expr = Expression {
+                code : String::from(""),
+                name,
+                expr_type : Type::Float,
             };
             return Ok(expr);
         }
+        // new int[n] or new float[n], an array that lives as long as something refers to it.
+        Token::New => {
+            let element_type = match next_result(tokens, index)? {
+                Token::Int => Type::Int,
+                Token::Float => Type::Float,
+                _ => return Err(String::from("'new' creates int or float arrays, expected 'int' or 'float'")),
+            };
+            if !matches!(next_result(tokens, index)?, Token::LeftBracket) {
+                return Err(String::from("expected '[' after the type of 'new'"));
+            }
+            let length = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+            if !length.expr_type.is_integer() {
+                return Err(String::from("length of array must be an int"));
+            }
+            if length.name.parse::<i32>().is_ok_and(|num| num <= 0) {
+                return Err(String::from("Arrays have to be at least one element long"));
+            }
+            if !matches!(next_result(tokens, index)?, Token::RightBracket) {
+                return Err(String::from("expected ']'"));
+            }
+            if matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+                return Err(String::from("'new' only creates one-dimensional arrays"));
+            }
+            let temp = create_temp();
+            Ok(Expression {
+                code: length.code + &format!("{}[] {temp}\n%new {temp}, {}\n", element_type.declaration(), length.name),
+                name: temp,
+                expr_type: element_type.array(),
+            })
+        }
+        // len(array) is the length of the first dimension.
+        Token::Len => {
+            if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                return Err(String::from("expected '(' after 'len'"));
+            }
+            // len(rows[i]) or len(f(x)), an array that is only known at runtime.
+            if !matches!((peek_result(tokens, *index)?, peek_result(tokens, *index + 1)?), (Token::Ident(_), Token::RightParen)) {
+                let array = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if array.expr_type.element().is_none() {
+                    return Err(String::from("'len' expects an array"));
+                }
+                if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                    return Err(String::from("expected ')'"));
+                }
+                let temp = create_temp();
+                return Ok(Expression {
+                    code : array.code + &format!("%int {temp}\n%len {temp}, {}\n", array.name),
+                    name : temp,
+                    expr_type : Type::Int,
+                });
+            }
+            let array = match next_result(tokens, index)? {
+                Token::Ident(ident) => match find_symbol(arr_table, ident) {
+                    Some(array) => array.clone(),
+                    None => return Err(format!("Array {ident} undeclared")),
+                },
+                _ => return Err(String::from("'len' expects an array")),
+            };
+            if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                return Err(String::from("expected ')'"));
+            }
+
+            Ok(array_length(&array, definitions))
+        }
+        // type conversion int(expression) or float(expression)
+        Token::Int | Token::Float => {
+            let to = if matches!(tokens[*index - 1], Token::Int) { Type::Int } else { Type::Float };
+            if !matches!(next_result(tokens, index)?, Token::LeftParen) {
+                return Err(format!("expected '(' after '{to}' conversion"));
+            }
+            let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+            if !matches!(next_result(tokens, index)?, Token::RightParen) {
+                return Err(String::from("expected ')'"));
+            }
+            if expr.expr_type.element().is_some() {
+                return Err(format!("cannot convert an array to {to}"));
+            }
+            if expr.expr_type == Type::Func {
+                return Err(format!("cannot convert a function reference to {to}"));
+            }
+            Ok(convert(expr, to))
+        }
         Token::LeftParen => {
-            let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table)?;
+            let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
             if !matches!(next_result(tokens, index)?, Token::RightParen) {
                 return Err(String::from("expected ')'"));
             }
@@ -1002,58 +2349,722 @@ fn parse_term(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Str
     }
 }
 
-// Array or int declaration
-fn parse_declaration(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, arr_table: &mut Vec<String>) -> Result<String, String> { 
-    match next_result(tokens, index)? {
-        Token::Int => {
-            match next_result(tokens, index)? {
-                Token::LeftBracket => {  // Array size is declared after 'int'
-                    let size: i32;
-                    match next_result(tokens, index)? {
-                        Token::Num(num) => {
-                            if *num <= 0 {
-                                return Err(String::from("Arrays have to be at least one element long"));
+// length of the first dimension of an array.
+fn array_length(array: &Variable, definitions: &Definitions) -> Expression {
+    let length = &array.dims[0];
+    if length.parse::<i32>().is_ok() || (!length.is_empty() && array.dims.len() > 1) {
+        // known at compile time, or already held in a variable.
+        return Expression {
+            code : String::from(""),
+            name : length.clone(),
+            expr_type : Type::Int,
+        };
+    }
+
+    // parameters and runtime sized arrays ask the interpreter.
+    let name = ir_array_name(array, definitions);
+    let temp = create_temp();
+    Expression {
+        code : format!("%int {temp}\n%len {temp}, {name}\n"),
+        name : temp,
+        expr_type : Type::Int,
+    }
+}
+
+// an array used as a value, i.e. 'h = values' or 'return values'. the elements are not copied.
+fn array_value(array: &Variable) -> Result<Expression, String> {
+    if array.dims.len() != 1 {
+        return Err(format!("only one-dimensional arrays can be used as a value, '{}' has {} dimensions", array.name, array.dims.len()));
+    }
+    match array.var_type {
+        Type::Int | Type::Float => Ok(Expression {
+            code: String::new(),
+            name: array.name.clone(),
+            expr_type: array.var_type.array(),
+        }),
+        _ => Err(format!("array '{}' needs an index", array.name)),
+    }
+}
+
+// number of arguments of a function from the math library, None for other names.
+fn intrinsic_arity(name: &str) -> Option<usize> {
+    match name {
+        "rand" | "getc" | "eof" => Some(0),
+        "abs" | "sqrt" | "arg" => Some(1),
+        "min" | "max" | "pow" | "gcd" => Some(2),
+        _ => None,
+    }
+}
+
+// constants are replaced by their literal, captured variables are read from their environment.
+fn read_variable(variable: &Variable) -> Expression {
+    match &variable.env {
+        Some(env) => {
+            let temp = create_temp();
+            Expression {
+                code: format!("{} {temp}\n%mov {temp}, {env}\n", variable.var_type.declaration()),
+                name: temp,
+                expr_type: variable.var_type,
+            }
+        }
+        None => Expression {
+            code: String::new(),
+            name: variable.value.clone().unwrap_or(variable.name.clone()),
+            expr_type: variable.var_type,
+        },
+    }
+}
+
+// the name of a function used as a value, i.e. apply(square, 5).
+fn function_reference(function_table: &[Function], ident: &String) -> Result<Expression, String> {
+    let overloads: Vec<&Function> = function_table.iter().filter(|f| f.name.eq(ident)).collect();
+    let function = match overloads[..] {
+        [function] => function,
+        [] => return Err(format!("Error. undeclared variable {ident}")),
+        _ => return Err(format!("function {ident} is overloaded, so it cannot be used as a function reference")),
+    };
+    if function.params.iter().any(|param| !param.dims.is_empty()) {
+        return Err(format!("function {ident} takes an array, so it cannot be used as a function reference"));
+    }
+    if function.return_types.iter().any(|return_type| return_type.element().is_some()) {
+        return Err(format!("function {ident} returns an array, so it cannot be used as a function reference"));
+    }
+    // %icall passes ints and returns a single int.
+    if let Some(param) = function.params.iter().find(|param| !param.var_type.is_integer()) {
+        return Err(format!("function {ident} takes a {} argument, so it cannot be used as a function reference", param.var_type));
+    }
+    if function.return_types.len() > 1 || function.return_types.iter().any(|return_type| !return_type.is_integer()) {
+        return Err(format!("function {ident} does not return a single int, so it cannot be used as a function reference"));
+    }
+    if !function.hidden.is_empty() {
+        return Err(format!("function {ident} uses variables of the function it is declared in, so it cannot be used as a function reference"));
+    }
+    let temp = create_temp();
+    Ok(Expression {
+        code: format!("%int {temp}\n%funcref {temp}, {}\n", function.ir_name),
+        name: temp,
+        expr_type: Type::Func,
+    })
+}
+
+// the arguments of a call to a user function, after its '('.
+// the overload is chosen by the number of arguments, left out parameters get their default value.
+// returns the code computing the arguments, 'func(a, b)' for the %call instruction and the return types.
+fn parse_call(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions, ident: &String) -> Result<(String, String, Vec<Type>), String> {
+    let count = count_arguments(tokens, *index)?;
+    let (ir_name, params, return_types, hidden) = match find_overload(function_table, ident, count) {
+        Ok(function) => (function.ir_name.clone(), function.params.clone(), function.return_types.clone(), function.hidden.clone()),
+        Err(e) => return Err(e),
+    };
+
+    let mut args_code = String::new();
+    let mut args = Vec::new();
+
+    // Parse all arguments
+    loop {
+        if matches!(peek_result(tokens, *index)?, Token::RightParen) {
+            *index += 1; // Consume ')'
+            break;
+        }
+        if args.len() >= params.len() {
+            return Err(format!("too many arguments passed to function {ident}, expected {}", params.len()));
+        }
+        let param = &params[args.len()];
+        if param.dims.is_empty() {
+            let arg_expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+            let arg_expr = coerce(arg_expr, param.var_type, &format!("argument {} of {ident}", args.len() + 1))?;
+            args_code += &arg_expr.code;
+            args.push(arg_expr.name);
+        } else {
+            // arrays are passed by reference, the callee works on the same array.
+            let arg_expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+            let arg_expr = coerce(arg_expr, param.var_type.array(), &format!("argument {} of {ident}", args.len() + 1))?;
+            args_code += &arg_expr.code;
+            args.push(arg_expr.name);
+        }
+
+        if matches!(peek_result(tokens, *index)?, Token::Comma) {
+            *index += 1; // Consume ','
+        } else if matches!(peek_result(tokens, *index)?, Token::RightParen) {
+            *index += 1; // Consume ')'
+            break;
+        } else {
+            return Err(String::from("expected ',' or ')' in function call"));
+        }
+    }
+
+    for param in &params[args.len()..] {
+        match &param.value {
+            Some(default) => args.push(default.clone()),
+            None => return Err(format!("too few arguments passed to function {ident}, expected {}", params.len())),
+        }
+    }
+
+    args.extend(hidden.into_iter().map(|array| array.name));
+
+    Ok((args_code, format!("{ir_name}({})", args.join(", ")), return_types))
+}
+
+// (expression, expression, ...) after the name of a function.
+fn parse_arguments(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Vec<Expression>, String> {
+    let mut args = vec![];
+    if matches!(peek_result(tokens, *index)?, Token::RightParen) {
+        *index += 1;
+        return Ok(args);
+    }
+    loop {
+        args.push(parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?);
+        match next_result(tokens, index)? {
+            Token::Comma => {}
+            Token::RightParen => return Ok(args),
+            _ => return Err(String::from("expected ',' or ')' in function call")),
+        }
+    }
+}
+
+// the IR of an asm block, copied line by line. variables, arrays and functions are replaced by their IR names,
+// other identifiers are left alone. every line is checked with the IR lexer, so that mistakes are reported
+// against the source line instead of the generated IR.
+fn parse_asm(text: &str, line: usize, symbol_table: &Vec<Variable>, function_table: &[Function], arr_table: &Vec<Variable>, definitions: &Definitions) -> Result<String, String> {
+    use interpreter::IRTok;
+    let mut code = String::new();
+    // the variables declared by the block itself, which the compiler doesn't know about.
+    let mut declared: Vec<String> = vec![];
+    for (offset, source) in text.lines().enumerate() {
+        let line = line + offset;
+        // the IR lexer expects every instruction to end with a newline.
+        let source = format!("{}\n", source.trim());
+        let mut rest = source.as_str();
+        let mut instruction = String::new();
+        let mut first = true;
+        let (mut first_is_variable, mut is_mov, mut declaration) = (false, false, false);
+        // which operand the token belongs to, the first one is the destination of most instructions.
+        let mut operand = 0;
+        let mut depth = 0;
+        // array elements and captured variables, %mov cannot copy from memory to memory.
+        let mut memory = 0;
+        loop {
+            let start = rest.len() - rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n').len();
+            let (token, next) = match interpreter::lex_ir_token(rest) {
+                (Some(token), next) => (token, next),
+                (None, _) => return Err(format!("asm block, line {line}: cannot read '{}'", rest.trim())),
+            };
+            let consumed = &rest[..rest.len() - next.len()];
+            match &token {
+                IRTok::EndInstr => {
+                    instruction += consumed;
+                    break;
+                }
+                IRTok::InvalidInstruction(opcode) => return Err(format!("asm block, line {line}: invalid instruction opcode '{opcode}'")),
+                IRTok::Func | IRTok::EndFunc => return Err(format!("asm block, line {line}: functions cannot be declared in an asm block")),
+                IRTok::Var(_) | IRTok::Num(_) | IRTok::FloatNum(_) | IRTok::Str(_) | IRTok::Comma | IRTok::Plus
+                | IRTok::LBrace | IRTok::RBrace | IRTok::LParen | IRTok::RParen if first => {
+                    return Err(format!("asm block, line {line}: instructions must begin with an opcode such as %mov or a label"));
+                }
+                _ => {}
+            }
+            if first {
+                // the first operand is written to, except by these. %out only takes a variable as well.
+                first_is_variable = !matches!(token, IRTok::OutStr | IRTok::OutVal | IRTok::Putc | IRTok::Srand | IRTok::Jump
+                    | IRTok::BranchIf | IRTok::BranchIfNot | IRTok::Return | IRTok::Assert | IRTok::Exit | IRTok::Bounds);
+                is_mov = matches!(token, IRTok::Mov);
+                declaration = matches!(token, IRTok::Int | IRTok::Float | IRTok::IntArray | IRTok::FloatArray | IRTok::IntHandleArray | IRTok::FloatHandleArray);
+            }
+            first = false;
+            match &token {
+                IRTok::Var(name) => {
+                    let dest = operand == 0 && depth == 0;
+                    let ir_name = if declaration && dest {
+                        declared.push(name.clone());
+                        name.clone()
+                    } else if declared.contains(name) {
+                        name.clone()
+                    } else {
+                        if let Some(variable) = find_symbol(symbol_table, name).or_else(|| find_symbol(&definitions.constants, name)) {
+                            if variable.value.is_some() && dest && first_is_variable {
+                                return Err(format!("asm block, line {line}: {name} is a constant, not a variable"));
+                            }
+                            if variable.env.is_some() {
+                                if !is_mov || depth > 0 {
+                                    return Err(format!("asm block, line {line}: {name} is used by a nested function, it can only be read or written with %mov"));
+                                }
+                                memory += 1;
                             }
-                            size = *num;
-                        },
-                        _ => {
-                            return Err(String::from("Error: expected Token::Num as length of array"));
                         }
+                        asm_name(name, symbol_table, function_table, arr_table, definitions).map_err(|e| format!("asm block, line {line}: {e}"))?
+                    };
+                    instruction += &consumed[..start];
+                    instruction += &ir_name;
+                    instruction += &consumed[start + name.len()..];
+                }
+                IRTok::LBrace => {
+                    if depth == 0 {
+                        memory += 1;
                     }
+                    depth += 1;
+                    instruction += consumed;
+                }
+                IRTok::RBrace => {
+                    depth -= 1;
+                    instruction += consumed;
+                }
+                IRTok::Comma if depth == 0 => {
+                    operand += 1;
+                    instruction += consumed;
+                }
+                _ => instruction += consumed,
+            }
+            if is_mov && memory > 1 {
+                return Err(format!("asm block, line {line}: %mov cannot copy from memory to memory, and variables used by a nested function are kept in memory"));
+            }
+            rest = next;
+        }
+        if !instruction.trim().is_empty() {
+            code += &instruction;
+        }
+    }
+    Ok(code)
+}
 
-                    if !matches!(next_result(tokens, index)?, Token::RightBracket) {
-                        return Err(String::from("expect ']' closing array size declaration"));
+// the IR name of an identifier used in an asm block.
+fn asm_name(name: &String, symbol_table: &Vec<Variable>, function_table: &[Function], arr_table: &Vec<Variable>, definitions: &Definitions) -> Result<String, String> {
+    if let Some(variable) = find_symbol(symbol_table, name).or_else(|| find_symbol(&definitions.constants, name)) {
+        // captured variables live in their environment, constants are replaced by their value.
+        return Ok(variable.env.clone().or(variable.value.clone()).unwrap_or(variable.name.clone()));
+    }
+    if find_symbol(arr_table, name).is_some() {
+        return Ok(name.clone());
+    }
+    let overloads: Vec<&Function> = function_table.iter().filter(|f| f.name.eq(name)).collect();
+    match overloads[..] {
+        [] => Err(format!("{name} is not declared")),
+        [function] if function.hidden.is_empty() => Ok(function.ir_name.clone()),
+        [_] => Err(format!("function {name} uses variables of the function it is declared in, so it cannot be called from an asm block")),
+        _ => Err(format!("function {name} is overloaded, use the IR name of one of its overloads")),
+    }
+}
+
+// %d prints an int, %f a float and %% a single '%'.
+// the text in between is printed with %out_str and each argument with %out_val, neither adds a newline.
+fn printf(format: &str, args: Vec<Expression>) -> Result<String, String> {
+    let mut code = String::new();
+    let mut output = String::new();
+    let mut args = args.into_iter();
+    let mut count = 0;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let specifier = match chars.next() {
+            Some('%') => {
+                output.push('%');
+                continue;
+            }
+            Some(specifier @ ('d' | 'f')) => specifier,
+            Some(specifier) => return Err(format!("unknown format specifier '%{specifier}' in printf")),
+            None => return Err(String::from("printf format ends with a single '%'")),
+        };
+        count += 1;
+        let expr = match args.next() {
+            Some(expr) => expr,
+            None => return Err(String::from("printf format has more specifiers than arguments")),
+        };
+        match (specifier, expr.expr_type) {
+            ('d', expr_type) if expr_type.is_integer() => {}
+            ('f', Type::Float) => {}
+            ('d', expr_type) => return Err(format!("printf argument {count} has type {expr_type}, but '%d' expects an int")),
+            (_, expr_type) => return Err(format!("printf argument {count} has type {expr_type}, but '%f' expects a float")),
+        }
+        if !output.is_empty() {
+            code += &format!("%out_str \"{}\"\n", output.escape_default());
+            output.clear();
+        }
+        code += &format!("{}%out_val {}\n", expr.code, expr.name);
+    }
+    if args.next().is_some() {
+        return Err(String::from("printf has more arguments than format specifiers"));
+    }
+    if !output.is_empty() {
+        code += &format!("%out_str \"{}\"\n", output.escape_default());
+    }
+    Ok(code)
+}
+
+// rand, getc, eof, arg, abs, min, max, pow, sqrt and gcd lower to their own IR instructions.
+// getc returns -1 at the end of the input, arg(i) is the i-th program argument.
+// min, max and pow work on ints or floats like the arithmetic operators.
+fn intrinsic(name: &str, mut args: Vec<Expression>) -> Result<Expression, String> {
+    if args.iter().any(|arg| !matches!(arg.expr_type, Type::Int | Type::Float | Type::Enum(_))) {
+        return Err(format!("{name} requires int or float arguments"));
+    }
+    if args.len() == 2 {
+        let e2 = args.pop().unwrap();
+        let e = args.pop().unwrap();
+        if name == "gcd" && (e.expr_type == Type::Float || e2.expr_type == Type::Float) {
+            return Err(String::from("gcd requires int arguments"));
+        }
+        return binary_operation(e, e2, name);
+    }
+
+    // %rand temp, %getc temp or %eof temp
+    if args.is_empty() {
+        let temp = create_temp();
+        return Ok(Expression {
+            code: format!("%int {temp}\n%{name} {temp}\n"),
+            name: temp,
+            expr_type: Type::Int,
+        });
+    }
+
+    // %abs temp, src  or  %sqrt temp, src  or  %arg temp, index
+    let e = args.pop().unwrap();
+    let (e, instruction) = match name {
+        "sqrt" => (convert(e, Type::Float), "%sqrt"),
+        "arg" if e.expr_type == Type::Float => return Err(String::from("arg expects an int index")),
+        "arg" => (convert(e, Type::Int), "%arg"),
+        _ if e.expr_type == Type::Float => (e, "%fabs"),
+        _ => (convert(e, Type::Int), "%abs"),
+    };
+    let temp = create_temp();
+    let mut code = e.code;
+    code += &format!("{} {temp}\n", e.expr_type.declaration());
+    code += &format!("{instruction} {temp}, {}\n", e.name);
+    Ok(Expression {
+        code,
+        name: temp,
+        expr_type: e.expr_type,
+    })
+}
+
+// arr[i] or arr[i][j]...
+// computes the row-major index into the flattened array: (i * columns) + j
+// multi-dimensional arrays check each index against its own dimension with %bounds,
+// the interpreter only knows the total length.
+fn parse_array_index(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions, array: &Variable) -> Result<Expression, String> {
+    let mut flat = Expression {
+        code: String::new(),
+        name: String::new(),
+        expr_type: Type::Int,
+    };
+    for (dimension, length) in array.dims.iter().enumerate() {
+        if !matches!(next_result(tokens, index)?, Token::LeftBracket) {
+            return Err(format!("array '{}' has {} dimensions, but only {} were indexed", array.name, array.dims.len(), dimension));
+        }
+        let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+        if !expr.expr_type.is_integer() {
+            return Err(format!("index of array '{}' must be an int", array.name));
+        }
+        if !matches!(next_result(tokens, index)?, Token::RightBracket) {
+            return Err(String::from("expected ']'"));
+        }
+
+        flat.code += &expr.code;
+        if array.dims.len() > 1 {
+            flat.code += &format!("%bounds {}, {}, {}, {}\n", ir_array_name(array, definitions), dimension + 1, expr.name, length);
+        }
+        if dimension == 0 {
+            flat.name = expr.name;
+        } else {
+            let operand = |name: &String| Expression { code: String::new(), name: name.clone(), expr_type: Type::Int };
+            let row = binary_operation(operand(&flat.name), operand(length), "mult")?;
+            let element = binary_operation(row, operand(&expr.name), "add")?;
+            flat.code += &element.code;
+            flat.name = element.name;
+        }
+    }
+    // the elements of an array of arrays are indexed again, see parse_element.
+    if array.var_type.element().is_none() && matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+        return Err(format!("array '{}' only has {} dimensions", array.name, array.dims.len()));
+    }
+    Ok(flat)
+}
+
+// an array of structs is stored as one array per field, all of the same length.
+// returns the IR array that stands for the whole array.
+fn ir_array_name(array: &Variable, definitions: &Definitions) -> String {
+    match array.var_type {
+        Type::Struct(id) => format!("{}.{}", array.name, definitions.structs[id].fields[0].name),
+        _ => array.name.clone(),
+    }
+}
+
+// arr[i][j], points[i].x or rows[i][j] where 'rows' is an array of arrays.
+// an element that is itself an array is moved into a temporary before it is indexed.
+fn parse_element(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions, array: &Variable) -> Result<Lvalue, String> {
+    let element = parse_array_index(tokens, index, symbol_table, function_table, arr_table, definitions, array)?;
+    let array = parse_field(tokens, index, &definitions.structs, array)?;
+    let mut lvalue = Lvalue {
+        code: element.code,
+        name: format!("[{} + {}]", array.name, element.name),
+        lvalue_type: array.var_type,
+    };
+    while let Some(element_type) = lvalue.lvalue_type.element() {
+        if !matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+            break;
+        }
+        let temp = create_temp();
+        lvalue.code += &format!("{} {temp}\n%mov {temp}, {}\n", lvalue.lvalue_type.declaration(), lvalue.name);
+        let inner = Variable { name: temp, var_type: element_type, dims: vec![String::new()], value: None, env: None };
+        let element = parse_array_index(tokens, index, symbol_table, function_table, arr_table, definitions, &inner)?;
+        lvalue.code += &element.code;
+        lvalue.name = format!("[{} + {}]", inner.name, element.name);
+        lvalue.lvalue_type = element_type;
+    }
+    Ok(lvalue)
+}
+
+// q, r = divmod(a, b), every value returned by the function is assigned to its own lvalue.
+fn parse_multiple_assignment(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions, first: Lvalue) -> Result<String, String> {
+    let mut dests = vec![first];
+    while matches!(peek_result(tokens, *index)?, Token::Comma) {
+        *index += 1;
+        dests.push(parse_lvalue(tokens, index, symbol_table, function_table, arr_table, definitions)?);
+    }
+    if !matches!(next_result(tokens, index)?, Token::Assign) {
+        return Err(String::from("expected '=' after the variables of a multiple assignment"));
+    }
+    let ident = match (next_result(tokens, index)?, peek_result(tokens, *index)?) {
+        (Token::Ident(ident), Token::LeftParen) => ident,
+        _ => return Err(String::from("a multiple assignment needs a function call on the right")),
+    };
+    *index += 1;
+
+    let mut code = String::new();
+    for dest in &dests {
+        code += &dest.code;
+    }
+    let (args_code, call, return_types) = parse_call(tokens, index, symbol_table, function_table, arr_table, definitions, ident)?;
+    if return_types.len() != dests.len() {
+        return Err(format!("function {ident} returns {} values, but {} are assigned", return_types.len(), dests.len()));
+    }
+    code += &args_code;
+    let mut temps = vec![];
+    for return_type in &return_types {
+        let temp = create_temp();
+        code += &format!("{} {temp}\n", return_type.declaration());
+        temps.push(temp);
+    }
+    code += &format!("%call {}, {}\n", temps.join(", "), call);
+    for ((dest, temp), return_type) in dests.iter().zip(temps).zip(return_types) {
+        let value = Expression { code: String::new(), name: temp, expr_type: return_type };
+        let value = coerce(value, dest.lvalue_type, &format!("assignment to '{}'", dest.name))?;
+        code += &format!("{}%mov {}, {}\n", value.code, dest.name, value.name);
+    }
+    Ok(code)
+}
+
+// x, arr[i][j], p.x or points[i].x as the destination of an assignment or read.
+fn parse_lvalue(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Lvalue, String> {
+    let ident = match next_result(tokens, index)? {
+        Token::Ident(ident) => ident,
+        _ => return Err(String::from("expected a variable")),
+    };
+    if matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+        // [array + i]
+        let array = match find_symbol(arr_table, ident) {
+            Some(array) => array.clone(),
+            None => return Err(format!("Array '{ident}' not declared")),
+        };
+        return parse_element(tokens, index, symbol_table, function_table, arr_table, definitions, &array);
+    }
+
+    let variable = match find_symbol(symbol_table, ident).or_else(|| find_symbol(&definitions.constants, ident)) {
+        Some(variable) => parse_field(tokens, index, &definitions.structs, variable)?,
+        None => match find_symbol(arr_table, ident) {
+            // int[] h can be made to refer to another array, arrays with a size cannot.
+            Some(array) if array.dims == [""] && matches!(array.var_type, Type::Int | Type::Float) => {
+                if array.env.is_some() {
+                    return Err(format!("array '{ident}' belongs to the enclosing function, so it cannot be assigned here"));
+                }
+                return Ok(Lvalue {
+                    code: String::new(),
+                    name: array.name.clone(),
+                    lvalue_type: array.var_type.array(),
+                });
+            }
+            Some(_) => return Err(format!("array '{ident}' needs an index")),
+            None => return Err(format!("Variable not declared: {ident}")),
+        },
+    };
+    if variable.value.is_some() {
+        return Err(format!("cannot assign to constant {ident}"));
+    }
+    Ok(Lvalue {
+        code: String::new(),
+        name: variable.env.unwrap_or(variable.name),
+        lvalue_type: variable.var_type,
+    })
+}
+
+// p.x or points[i].x
+// a struct has to be followed by one of its fields, other variables are returned unchanged.
+fn parse_field(tokens: &Vec<Token>, index: &mut usize, struct_table: &[Struct], variable: &Variable) -> Result<Variable, String> {
+    let id = match variable.var_type {
+        Type::Struct(id) => id,
+        _ => {
+            if matches!(peek_result(tokens, *index)?, Token::Dot) {
+                return Err(format!("'{}' is not a struct", variable.name));
+            }
+            return Ok(variable.clone());
+        }
+    };
+    let definition = &struct_table[id];
+    if !matches!(next_result(tokens, index)?, Token::Dot) {
+        return Err(format!("struct '{}' cannot be used as a value, expected a field of '{}'", variable.name, definition.name));
+    }
+    let field = match next_result(tokens, index)? {
+        Token::Ident(field) => field,
+        _ => return Err(String::from("expected field name after '.'")),
+    };
+    match find_symbol(&definition.fields, field) {
+        Some(field) => Ok(Variable {
+            name: format!("{}.{}", variable.name, field.name),
+            var_type: field.var_type,
+            dims: variable.dims.clone(),
+            value: None,
+            env: None,
+        }),
+        None => Err(format!("struct '{}' has no field '{field}'", definition.name)),
+    }
+}
+
+// const int N = 10 * 4;
+// the value is evaluated at compile time, no code is generated for a constant.
+fn parse_constant(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Variable, String> {
+    if !matches!(next_result(tokens, index)?, Token::Const) {
+        return Err(String::from("constants must begin with const"));
+    }
+    let var_type = match next_result(tokens, index)? {
+        Token::Int => Type::Int,
+        Token::Float => Type::Float,
+        _ => return Err(String::from("constants must be 'int' or 'float'")),
+    };
+    let name = match next_result(tokens, index)? {
+        Token::Ident(name) => name,
+        _ => return Err(String::from("expected identifier for constant")),
+    };
+    if !matches!(next_result(tokens, index)?, Token::Assign) {
+        return Err(format!("constant {name} must be initialised with '='"));
+    }
+    let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+    let expr = coerce(expr, var_type, &format!("constant '{name}'"))?;
+    if !expr.code.is_empty() || (expr.name.parse::<i32>().is_err() && expr.name.parse::<f64>().is_err()) {
+        return Err(format!("value of constant {name} must be known at compile time"));
+    }
+    if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+        return Err(String::from("expect ';' closing statement"));
+    }
+    Ok(Variable { name: name.clone(), var_type, dims: vec![], value: Some(expr.name), env: None })
+}
+
+// Array, int, float or struct declaration
+fn parse_declaration(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<String, String> { 
+    let var_type = match next_result(tokens, index)? {
+        Token::Int => Type::Int,
+        Token::Float => Type::Float,
+        Token::Ident(name) => match find_type(definitions, name) {
+            Some(var_type) => var_type,
+            None => return Err(format!("unknown type '{name}'")),
+        },
+        _ => return Err(String::from("invalid declaration, expected 'int' or 'float' type")),
+    };
+    match next_result(tokens, index)? {
+        Token::LeftBracket => {  // Array size is declared after the type, int[rows][columns] for more dimensions
+            let mut var_type = var_type;
+            if matches!(peek_result(tokens, *index)?, Token::RightBracket) {
+                *index += 1;
+                if !matches!(var_type, Type::Int | Type::Float) {
+                    return Err(format!("only int and float arrays can be declared without a size, found '{var_type}[]'"));
+                }
+                // int[] h; refers to no array until one is assigned to it.
+                if let Token::Ident(ident) = peek_result(tokens, *index)? {
+                    *index += 1;
+                    if find_symbol(arr_table, ident).is_some() || find_symbol(symbol_table, ident).is_some() {
+                        return Err(format!("Found a duplicate array variable {ident}"));
                     }
-                    // Expect identifier after array size
-                    match next_result(tokens, index)? {
-                        Token::Ident(ident) => {  // Variable name of the array
-                            if find_symbol(&arr_table, ident) {
-                                return Err(format!("Found a duplicate array variable {ident}"));
-                            }
-                            arr_table.push(ident.clone());
-                            let statement = format!("%int[] {}, {}\n", ident, size);
-                            Ok(statement)
-                        },
-                        _ => Err(String::from("expected identifier for array name")),
+                    arr_table.push(Variable { name: ident.clone(), var_type, dims: vec![String::new()], value: None, env: None });
+                    return Ok(format!("{}[] {}\n", var_type.declaration(), ident));
+                }
+                // int[][4] rows; an array of four arrays.
+                if !matches!(next_result(tokens, index)?, Token::LeftBracket) {
+                    return Err(String::from("expected an array name or size after 'int[]'"));
+                }
+                var_type = var_type.array();
+            }
+            // sizes can be any int expression, evaluated when the declaration runs.
+            let mut code = String::new();
+            let mut dims: Vec<String> = vec![];
+            loop {
+                let expr = parse_expression(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+                if !expr.expr_type.is_integer() {
+                    return Err(String::from("length of array must be an int"));
+                }
+                if let Ok(num) = expr.name.parse::<i32>() {
+                    if num <= 0 {
+                        return Err(String::from("Arrays have to be at least one element long"));
                     }
-                },
-                Token::Ident(ident) => {  // Normal int variable declaration
-                    if find_symbol(&symbol_table, ident) {
-                        return Err(format!("Found a duplicate variable {ident}"));
+                    dims.push(expr.name);
+                } else {
+                    // keep a copy, so that the array keeps its size even if the variable changes later.
+                    let temp = create_temp();
+                    code += &expr.code;
+                    code += &format!("%int {temp}\n%mov {temp}, {}\n", expr.name);
+                    dims.push(temp);
+                }
+
+                if !matches!(next_result(tokens, index)?, Token::RightBracket) {
+                    return Err(String::from("expect ']' closing array size declaration"));
+                }
+
+                if !matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+                    break;
+                }
+                *index += 1;
+            }
+
+            // Expect identifier after array size
+            match next_result(tokens, index)? {
+                Token::Ident(ident) => {  // Variable name of the array
+                    if find_symbol(arr_table, ident).is_some() {
+                        return Err(format!("Found a duplicate array variable {ident}"));
+                    }
+                    // %int[] array, rows, columns
+                    // the array is flattened into a single row-major IR array.
+                    match var_type {
+                        Type::Struct(id) => {
+                            for field in &definitions.structs[id].fields {
+                                code += &format!("{}[] {}.{}, {}\n", field.var_type.declaration(), ident, field.name, dims.join(", "));
+                            }
+                        }
+                        _ => code += &format!("{}[] {}, {}\n", var_type.declaration(), ident, dims.join(", ")),
                     }
-                    symbol_table.push(ident.clone());
-                    let statement = format!("%int {}\n", ident);
-                    Ok(statement)
+                    arr_table.push(Variable { name: ident.clone(), var_type, dims, value: None, env: None });
+                    Ok(code)
                 },
-                _ => Err(String::from("expected '[' for array declaration or identifier for int variable")),
+                _ => Err(String::from("expected identifier for array name")),
+            }
+        },
+        Token::Ident(ident) => {  // Normal int or float variable declaration
+            if find_symbol(symbol_table, ident).is_some() {
+                return Err(format!("Found a duplicate variable {ident}"));
             }
+            symbol_table.push(Variable { name: ident.clone(), var_type, dims: vec![], value: None, env: None });
+            let statement = match var_type {
+                Type::Struct(id) => definitions.structs[id].fields.iter()
+                    .map(|field| format!("{} {}.{}\n", field.var_type.declaration(), ident, field.name))
+                    .collect(),
+                _ => format!("{} {}\n", var_type.declaration(), ident),
+            };
+            Ok(statement)
         },
-        _ => Err(String::from("invalid declaration, expected 'int' type")),
+        _ => Err(String::from("expected '[' for array declaration or identifier for variable")),
     }
 }
 
 // term [bool operator] term
-fn parse_bool_operation(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<String>, function_table: &mut Vec<String>, arr_table: &mut Vec<String>) -> Result<Expression, String> {
+fn parse_bool_operation(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut Vec<Variable>, function_table: &mut Vec<Function>, arr_table: &mut Vec<Variable>, definitions: &Definitions) -> Result<Expression, String> {
     /*
         ** expr1 code
         ** expr2 code
@@ -1061,54 +3072,55 @@ fn parse_bool_operation(tokens: &Vec<Token>, index: &mut usize, symbol_table: &m
         %lt temp, expr1.name, expr2.code
     */
     
-    let mut expr_ret = Expression {
-        code : format!(""),
-        name : format!(""),
-    };
-    let expr1 = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
-    expr_ret.code += &expr1.code;
-    let b_operator = next_result(tokens, index)?;
-    let expr2 = parse_term(tokens, index, symbol_table, function_table, arr_table)?;
-    expr_ret.code += &expr2.code;
-    let temp = create_temp();
-    expr_ret.code += &format!("%int {temp}\n");
-    match b_operator {
-        Token::Equality => {
-            expr_ret.code += &format!("%eq {temp}, {}, {}\n", expr1.name, expr2.name);
-        },
-        Token::NotEqual => {
-            expr_ret.code += &format!("%neq {temp}, {}, {}\n", expr1.name, expr2.name);
-        },      
-        Token::Greater => {
-            expr_ret.code += &format!("%gt {temp}, {}, {}\n", expr1.name, expr2.name);
-        },
-        Token::GreaterEqual => {
-            expr_ret.code += &format!("%ge {temp}, {}, {}\n", expr1.name, expr2.name);
-        },
-        Token::Less => {
-            expr_ret.code += &format!("%lt {temp}, {}, {}\n", expr1.name, expr2.name);
-        },
-        Token::LessEqual => {
-            expr_ret.code += &format!("%le {temp}, {}, {}\n", expr1.name, expr2.name);
-        },
+    let expr1 = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
+    let opcode = match next_result(tokens, index)? {
+        Token::Equality => "eq",
+        Token::NotEqual => "neq",
+        Token::Greater => "gt",
+        Token::GreaterEqual => "ge",
+        Token::Less => "lt",
+        Token::LessEqual => "le",
 
         _ => { 
             return Err(String::from("expect boolean operator (i.e. \"<\" or \"==\")..."));
         }
     };
+    let expr2 = parse_term(tokens, index, symbol_table, function_table, arr_table, definitions)?;
 
-    expr_ret.name = temp;
-
-    return Ok(expr_ret);
+    binary_operation(expr1, expr2, opcode)
 }
 
 // parse programs with multiple functions
 // loop over everything, outputting generated code.
 fn parse_program(tokens: &Vec<Token>, index: &mut usize) -> Result<String, String> {
     let mut generated_code = String::from("");
-    let mut function_table: Vec<String> = vec![];
+    let mut function_table: Vec<Function> = vec![];
+    let mut definitions = Definitions {
+        structs: vec![],
+        enums: vec![],
+        constants: vec![],
+    };
     loop {
-        match parse_function(tokens, index, &mut function_table)? {
+        match peek(tokens, *index) {
+            Some(Token::Struct) => {
+                parse_struct(tokens, index, &mut definitions)?;
+                continue;
+            }
+            Some(Token::Enum) => {
+                parse_enum(tokens, index, &mut definitions)?;
+                continue;
+            }
+            Some(Token::Const) => {
+                let constant = parse_constant(tokens, index, &mut vec![], &mut function_table, &mut vec![], &definitions)?;
+                if find_symbol(&definitions.constants, &constant.name).is_some() {
+                    return Err(format!("Found a duplicate constant {}", constant.name));
+                }
+                definitions.constants.push(constant);
+                continue;
+            }
+            _ => {}
+        }
+        match parse_function(tokens, index, &mut function_table, &definitions, None)? {
             None => {
                 break;
             }
@@ -1118,26 +3130,174 @@ fn parse_program(tokens: &Vec<Token>, index: &mut usize) -> Result<String, Strin
         }
     }
 
-    if !find_symbol(&function_table, &format!("main")){
+    if find_function(&function_table, "main").is_none() {
         return Err(format!("Missing 'main' function"));
     }
 
     return Ok(generated_code);
 }
 
-fn find_symbol(symbol_table: &Vec<String>, symbol: &String) -> bool {
-    for s in symbol_table{
-        if s.eq(symbol){
-            return true;
+fn find_symbol<'a>(symbol_table: &'a Vec<Variable>, symbol: &String) -> Option<&'a Variable> {
+    symbol_table.iter().find(|s| s.name.eq(symbol))
+}
+
+fn find_function<'a>(function_table: &'a [Function], name: &str) -> Option<&'a Function> {
+    function_table.iter().find(|f| f.name.eq(name))
+}
+
+// the overload of a function that can be called with count arguments.
+fn find_overload<'a>(function_table: &'a [Function], name: &str, count: usize) -> Result<&'a Function, String> {
+    if find_function(function_table, name).is_none() {
+        return Err(format!("Function {name} not initialised"));
+    }
+    match function_table.iter().find(|f| f.name.eq(name) && f.arity().contains(&count)) {
+        Some(function) => Ok(function),
+        None => Err(format!("no overload of function {name} takes {count} arguments")),
+    }
+}
+
+impl Function {
+    // parameters with a default value can be left out of a call.
+    fn arity(&self) -> std::ops::RangeInclusive<usize> {
+        let required = self.params.iter().filter(|param| param.value.is_none()).count();
+        required..=self.params.len()
+    }
+}
+
+// the number of arguments of a call, starting after its '('.
+fn count_arguments(tokens: &Vec<Token>, index: usize) -> Result<usize, String> {
+    if matches!(peek_result(tokens, index)?, Token::RightParen) {
+        return Ok(0);
+    }
+    let mut count = 1;
+    let mut depth = 0;
+    let mut i = index;
+    loop {
+        match peek_result(tokens, i)? {
+            Token::LeftParen | Token::LeftBracket => depth += 1,
+            Token::RightParen if depth == 0 => return Ok(count),
+            Token::RightParen | Token::RightBracket => depth -= 1,
+            Token::Comma if depth == 0 => count += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+// a struct or enum name used as a type.
+fn find_type(definitions: &Definitions, name: &str) -> Option<Type> {
+    if let Some(id) = definitions.structs.iter().position(|s| s.name.eq(name)) {
+        return Some(Type::Struct(id));
+    }
+    if let Some(id) = definitions.enums.iter().position(|e| e.name.eq(name)) {
+        return Some(Type::Enum(id));
+    }
+    None
+}
+
+// the enum that declares an enumerator, and the enumerator's value.
+fn find_enumerator(definitions: &Definitions, name: &str) -> Option<(usize, usize)> {
+    for (id, definition) in definitions.enums.iter().enumerate() {
+        if let Some(value) = definition.enumerators.iter().position(|e| e.eq(name)) {
+            return Some((id, value));
+        }
+    }
+    None
+}
+
+// enum Color { Red, Green, Blue }
+// enumerators are numbered from 0 and are compile-time int constants.
+fn parse_enum(tokens: &Vec<Token>, index: &mut usize, definitions: &mut Definitions) -> Result<(), String> {
+    if !matches!(next_result(tokens, index)?, Token::Enum) {
+        return Err(String::from("enums must begin with enum"));
+    }
+    let name = match next_result(tokens, index)? {
+        Token::Ident(name) => name,
+        _ => return Err(String::from("enums must have an enum identifier")),
+    };
+    if find_type(definitions, name).is_some() {
+        return Err(format!("Error: Type {name} already declared"));
+    }
+    if !matches!(next_result(tokens, index)?, Token::LeftCurly) {
+        return Err(String::from("expected '{' for enum"));
+    }
+
+    let mut enumerators: Vec<String> = vec![];
+    loop {
+        match next_result(tokens, index)? {
+            Token::RightCurly => break,
+            Token::Ident(enumerator) => {
+                if enumerators.contains(enumerator) || find_enumerator(definitions, enumerator).is_some() {
+                    return Err(format!("Found a duplicate enumerator {enumerator}"));
+                }
+                enumerators.push(enumerator.clone());
+            }
+            _ => return Err(String::from("expected identifier for enumerator")),
+        }
+        match next_result(tokens, index)? {
+            Token::Comma => {}
+            Token::RightCurly => break,
+            _ => return Err(String::from("expected ',' or '}' after enumerator")),
+        }
+    }
+
+    if enumerators.is_empty() {
+        return Err(format!("enum {name} must have at least one enumerator"));
+    }
+    definitions.enums.push(Enum { name: name.clone(), enumerators });
+    Ok(())
+}
+
+// struct Point { int x; float y; }
+fn parse_struct(tokens: &Vec<Token>, index: &mut usize, definitions: &mut Definitions) -> Result<(), String> {
+    if !matches!(next_result(tokens, index)?, Token::Struct) {
+        return Err(String::from("structs must begin with struct"));
+    }
+    let name = match next_result(tokens, index)? {
+        Token::Ident(name) => name,
+        _ => return Err(String::from("structs must have a struct identifier")),
+    };
+    if find_type(definitions, name).is_some() {
+        return Err(format!("Error: Type {name} already declared"));
+    }
+    if !matches!(next_result(tokens, index)?, Token::LeftCurly) {
+        return Err(String::from("expected '{' for struct"));
+    }
+
+    let mut fields: Vec<Variable> = vec![];
+    loop {
+        let var_type = match next_result(tokens, index)? {
+            Token::RightCurly => break,
+            Token::Int => Type::Int,
+            Token::Float => Type::Float,
+            _ => return Err(String::from("struct fields must be 'int' or 'float'")),
+        };
+        let field = match next_result(tokens, index)? {
+            Token::Ident(field) => field,
+            _ => return Err(String::from("expected identifier for struct field")),
+        };
+        if find_symbol(&fields, field).is_some() {
+            return Err(format!("Found a duplicate field {field} in struct {name}"));
+        }
+        fields.push(Variable { name: field.clone(), var_type, dims: vec![], value: None, env: None });
+        if !matches!(next_result(tokens, index)?, Token::Semicolon) {
+            return Err(String::from("expect ';' closing struct field"));
         }
     }
-    return  false;
+
+    if fields.is_empty() {
+        return Err(format!("struct {name} must have at least one field"));
+    }
+    definitions.structs.push(Struct { name: name.clone(), fields });
+    Ok(())
 }
 
-fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut Vec<String>) -> Result<Option<String>, String> {
-    let mut symbol_table: Vec<String> = vec![];
-    let mut arr_table: Vec<String> = vec![];
-    let mut label_table: VecDeque<(String, String)> = VecDeque::new();
+fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut Vec<Function>, definitions: &Definitions, enclosing: Option<Enclosing>) -> Result<Option<String>, String> {
+    let mut symbol_table: Vec<Variable> = vec![];
+    let mut arr_table: Vec<Variable> = vec![];
+    // the IR of the functions declared inside this one, emitted after it.
+    let mut nested_code = String::new();
+    let mut label_table: VecDeque<LoopLabel> = VecDeque::new();
     
     match next(tokens, index) {
         None => {
@@ -1150,22 +3310,56 @@ fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut V
         }
         // Not an Error & not last token
     }
+
+    // optional return type, defaults to int: func float average(...)
+    // several values are returned with a list of types: func (int, int) divmod(...)
+    // arrays are returned by reference: func int[] range(...)
+    let mut return_types = vec![];
+    let several = matches!(peek_result(tokens, *index)?, Token::LeftParen);
+    if several {
+        *index += 1;
+    }
+    loop {
+        let return_type = match peek_result(tokens, *index)? {
+            Token::Int => Type::Int,
+            Token::Float => Type::Float,
+            _ if several => return Err(String::from("expected 'int' or 'float' in the return types")),
+            _ => break,
+        };
+        *index += 1;
+        if matches!(peek_result(tokens, *index)?, Token::LeftBracket) {
+            if !matches!(peek_result(tokens, *index + 1)?, Token::RightBracket) {
+                return Err(String::from("expected '[]' after the return type"));
+            }
+            *index += 2;
+            return_types.push(return_type.array());
+        } else {
+            return_types.push(return_type);
+        }
+        if !several {
+            break;
+        }
+        match next_result(tokens, index)? {
+            Token::Comma => {}
+            Token::RightParen => break,
+            _ => return Err(String::from("expected ',' or ')' in the return types")),
+        }
+    }
+    if return_types.is_empty() {
+        return_types.push(Type::Int);
+    }
+
     let func_ident = match next_result(tokens, index)? {
         Token::Ident(func_ident) => func_ident,
         _  => {return Err(String::from("functions must have a function identifier"));}
     };
 
-    if find_symbol(&function_table, func_ident){
-        return Err(format!("Error: Function {func_ident} already declared"));
-    }
-    function_table.push(func_ident.clone());
-
     if !matches !(next_result(tokens, index)?, Token::LeftParen) {
         return Err(String::from("expected '('"));
     }
 
-    let mut code = format!("%func {} (", func_ident);
-    let mut params: Vec<String> = vec![];
+    let mut code = String::new();
+    let mut params: Vec<Variable> = vec![];
 
     // function parameters
     loop {
@@ -1175,15 +3369,49 @@ fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut V
                 break;
             }
             
-            Token::Int => {
+            Token::Int | Token::Float => {
+                let param_type = if matches!(tokens[*index - 1], Token::Int) { Type::Int } else { Type::Float };
+                // int[] a, an array passed by reference.
+                let is_array = matches!(peek_result(tokens, *index)?, Token::LeftBracket);
+                if is_array {
+                    *index += 1;
+                    if !matches!(next_result(tokens, index)?, Token::RightBracket) {
+                        return Err(String::from("expected ']' in array parameter"));
+                    }
+                }
                 match next_result(tokens, index)? {
                     Token::Ident(param) => {
-                        if find_symbol(&symbol_table, param) {
+                        if find_symbol(&symbol_table, param).is_some() || find_symbol(&arr_table, param).is_some() {
                             return Err(format!("Found a duplicate variable {param}"));
                         }
-                        symbol_table.push(param.clone());
-                        code += &format!("%int {}", param);
-                        params.push(param.clone());
+                        let mut variable = if is_array {
+                            // the length is only known at runtime, see 'len'.
+                            let variable = Variable { name: param.clone(), var_type: param_type, dims: vec![String::new()], value: None, env: None };
+                            arr_table.push(variable.clone());
+                            code += &format!("{}[] {}", param_type.declaration(), param);
+                            variable
+                        } else {
+                            let variable = Variable { name: param.clone(), var_type: param_type, dims: vec![], value: None, env: None };
+                            symbol_table.push(variable.clone());
+                            code += &format!("{} {}", param_type.declaration(), param);
+                            variable
+                        };
+                        // int hi = 100, the default has to be known at compile time.
+                        if matches!(peek_result(tokens, *index)?, Token::Assign) {
+                            *index += 1;
+                            if is_array {
+                                return Err(format!("array parameter {param} cannot have a default value"));
+                            }
+                            let expr = parse_expression(tokens, index, &mut vec![], function_table, &mut vec![], definitions)?;
+                            let expr = coerce(expr, param_type, &format!("default value of '{param}'"))?;
+                            if !expr.code.is_empty() || (expr.name.parse::<i32>().is_err() && expr.name.parse::<f64>().is_err()) {
+                                return Err(format!("default value of {param} must be known at compile time"));
+                            }
+                            variable.value = Some(expr.name);
+                        } else if params.last().is_some_and(|last: &Variable| last.value.is_some()) {
+                            return Err(format!("parameter {param} needs a default value because the parameter before it has one"));
+                        }
+                        params.push(variable);
                         match peek_result(tokens, *index)? { // lookahead
                             Token::Comma => {
                                 code += &format!(", ");
@@ -1201,13 +3429,81 @@ fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut V
                 }
             }
 
+            // func f, a reference to a function.
+            Token::Func => {
+                let param = match next_result(tokens, index)? {
+                    Token::Ident(param) => param,
+                    _ => return Err(String::from("expected ident function parameter")),
+                };
+                if find_symbol(&symbol_table, param).is_some() || find_symbol(&arr_table, param).is_some() {
+                    return Err(format!("Found a duplicate variable {param}"));
+                }
+                if params.last().is_some_and(|last: &Variable| last.value.is_some()) {
+                    return Err(format!("parameter {param} needs a default value because the parameter before it has one"));
+                }
+                let variable = Variable { name: param.clone(), var_type: Type::Func, dims: vec![], value: None, env: None };
+                symbol_table.push(variable.clone());
+                code += &format!("%int {param}");
+                params.push(variable);
+                match peek_result(tokens, *index)? {
+                    Token::Comma => {
+                        code += ", ";
+                        *index += 1;
+                    }
+                    Token::RightParen => {}
+                    _ => return Err(String::from("expected ',' or ')'")),
+                }
+            }
+
             _ => {
-                return Err(String::from("expected 'int' keyword or ')' token"));
+                return Err(String::from("expected 'int', 'float' or 'func' keyword or ')' token"));
             }
        }
     }
 
-    code += &format!(")\n");
+    // the environments and arrays a nested function shares with the function it is declared in.
+    let mut hidden = vec![];
+    if let Some(enclosing) = &enclosing {
+        for array in &enclosing.hidden {
+            if !code.is_empty() {
+                code += ", ";
+            }
+            code += &format!("{}[] {}", array.var_type.declaration(), array.name);
+            arr_table.push(array.clone());
+        }
+        symbol_table.extend(enclosing.captured.iter().cloned());
+        hidden = enclosing.hidden.clone();
+    }
+
+    // overloads are told apart by their number of arguments, so their ranges cannot overlap.
+    let mut ir_name = match &enclosing {
+        Some(enclosing) => format!("{}.{func_ident}", enclosing.ir_name),
+        None => func_ident.clone(),
+    };
+    let overloads: Vec<&Function> = function_table.iter().filter(|f| f.name.eq(func_ident)).collect();
+    if !overloads.is_empty() {
+        let (required, total) = (params.iter().filter(|param| param.value.is_none()).count(), params.len());
+        if func_ident == "main" || overloads.iter().any(|f| *f.arity().start() <= total && required <= *f.arity().end()) {
+            return Err(format!("Error: Function {func_ident} already declared"));
+        }
+        ir_name = format!("{ir_name}.{total}");
+    }
+    let mut code = format!("%func {ir_name} ({code})\n");
+
+    if func_ident == "main" && (return_types != [Type::Int] || params.len() > 1 || params.iter().any(|param| param.var_type != Type::Int || !param.dims.is_empty())) {
+        return Err(String::from("'main' must return an int and take either no parameters or a single 'int argc'"));
+    }
+
+    // registered before the body is parsed so that the function can call itself.
+    let position = function_table.len();
+    let return_values = return_types.clone();
+    function_table.push(Function {
+        name: func_ident.clone(),
+        ir_name: ir_name.clone(),
+        params,
+        return_types,
+        hidden,
+    });
 
     if !matches!(next_result(tokens, index)?, Token::LeftCurly) {
         return Err(String::from("expected '{' for function"));
@@ -1215,7 +3511,16 @@ fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut V
 
     // Loop statements
     loop {
-        match parse_statement(tokens, index, &mut symbol_table, function_table, &mut arr_table, &mut label_table)? {
+        if is_nested_function(tokens, *index) {
+            if enclosing.is_some() {
+                return Err(String::from("functions can only be nested one level deep"));
+            }
+            let (lift_code, function_code) = parse_nested_function(tokens, index, &mut symbol_table, &arr_table, function_table, definitions, &ir_name)?;
+            code += &lift_code;
+            nested_code += &function_code;
+            continue;
+        }
+        match parse_statement(tokens, index, &mut symbol_table, function_table, &mut arr_table, definitions, &mut label_table)? {
             None => {
                 break;
             }
@@ -1225,15 +3530,150 @@ fn parse_function(tokens: &Vec<Token>, index: &mut usize, function_table: &mut V
         }
     }
 
+    // without a return at the end, a function returning several values returns 0 for each of them
+    // and arrays that refer to no array yet. the interpreter only returns a single 0 by itself.
+    if return_values.len() > 1 && !code.lines().last().is_some_and(|line| line.starts_with("%ret")) {
+        let mut values = vec![];
+        for return_type in return_values {
+            match return_type {
+                Type::Float => values.push(String::from("0.0")),
+                Type::IntArray | Type::FloatArray => {
+                    let temp = create_temp();
+                    code += &format!("{} {temp}\n", return_type.declaration());
+                    values.push(temp);
+                }
+                _ => values.push(String::from("0")),
+            }
+        }
+        code += &format!("%ret {}\n", values.join(", "));
+    }
     code += "%endfunc\n\n";
+    code += &nested_code;
 
     if !matches!(next_result(tokens, index)?, Token::RightCurly) {
       return Err(String::from("expected '}' after function"));
     }
 
+    if enclosing.is_some() {
+        // the enclosing function is still being parsed, so it has to stay the last one in the function table.
+        let last = function_table.len() - 1;
+        function_table.swap(last - 1, last);
+    } else {
+        // nested functions can only be called from inside the function they are declared in.
+        // they were kept in front of this function.
+        function_table.drain(position..function_table.len() - 1);
+    }
+
     return Ok(Some(code));
 }
 
+// what a nested function sees of the function it is declared in.
+struct Enclosing {
+    ir_name: String,
+    // the captured variables, each stored in an environment array.
+    captured: Vec<Variable>,
+    hidden: Vec<Variable>,
+}
+
+// func inside a function body, as opposed to the declaration of a function reference 'func f;'.
+fn is_nested_function(tokens: &Vec<Token>, index: usize) -> bool {
+    matches!(peek(tokens, index), Some(Token::Func))
+        && matches!((peek(tokens, index + 1), peek(tokens, index + 2)), (Some(Token::Int | Token::Float | Token::LeftParen), _) | (Some(Token::Ident(_)), Some(Token::LeftParen)))
+}
+
+// a function declared inside another one can read and write the variables declared before it.
+// those variables are lifted into environment arrays, shared by reference like any array:
+// the enclosing function copies their values in and from then on uses the environment as well.
+// returns the code lifting the variables and the IR of the nested function.
+fn parse_nested_function(tokens: &Vec<Token>, index: &mut usize, symbol_table: &mut [Variable], arr_table: &[Variable], function_table: &mut Vec<Function>, definitions: &Definitions, enclosing: &String) -> Result<(String, String), String> {
+    // the identifiers the nested function uses, leaving out the ones it declares itself.
+    let mut used: Vec<&String> = vec![];
+    let mut declared: Vec<&String> = vec![];
+    let mut name = None;
+    let mut depth = 0;
+    let mut i = *index + 1;
+    loop {
+        match peek_result(tokens, i)? {
+            Token::LeftCurly => depth += 1,
+            Token::RightCurly if depth == 1 => break,
+            Token::RightCurly => depth -= 1,
+            Token::Ident(ident) if name.is_none() => name = Some(ident),
+            Token::Ident(ident) => match &tokens[i - 1] {
+                Token::Int | Token::Float | Token::Func | Token::RightBracket | Token::For => declared.push(ident),
+                Token::Ident(type_name) if find_type(definitions, type_name).is_some() => declared.push(ident),
+                Token::Dot => {}
+                _ => used.push(ident),
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    let name = name.unwrap();
+    let captures = |variable: &Variable| used.contains(&&variable.name) && !declared.contains(&&variable.name);
+
+    let mut code = String::new();
+    let mut hidden: Vec<Variable> = vec![];
+    let mut captured = vec![];
+    for element_type in [Type::Int, Type::Float] {
+        let env = format!("{enclosing}.{name}.{}", if element_type == Type::Float { "fenv" } else { "env" });
+        let mut lifted = 0;
+        for variable in symbol_table.iter_mut() {
+            if !captures(variable) || variable.value.is_some() || (variable.var_type == Type::Float) != (element_type == Type::Float) {
+                continue;
+            }
+            if let Type::Struct(_) = variable.var_type {
+                return Err(format!("struct {} cannot be captured by the nested function {name}", variable.name));
+            }
+            if variable.env.is_none() {
+                code += &format!("%mov [{env} + {lifted}], {}\n", variable.name);
+                variable.env = Some(format!("[{env} + {lifted}]"));
+                lifted += 1;
+            }
+        }
+        if lifted > 0 {
+            code = format!("{}[] {env}, {lifted}\n", element_type.declaration()) + &code;
+            hidden.push(Variable { name: env, var_type: element_type, dims: vec![String::new()], value: None, env: None });
+        }
+    }
+    for variable in symbol_table.iter().filter(|variable| captures(variable)) {
+        // variables lifted for an earlier nested function stay in its environment.
+        if let Some(env) = &variable.env {
+            let array = &env[1..env.find(" + ").unwrap()];
+            if !hidden.iter().any(|h| h.name == array) {
+                let element_type = if variable.var_type == Type::Float { Type::Float } else { Type::Int };
+                hidden.push(Variable { name: String::from(array), var_type: element_type, dims: vec![String::new()], value: None, env: None });
+            }
+        }
+        captured.push(variable.clone());
+    }
+    for array in arr_table.iter().filter(|array| captures(array)) {
+        if let Type::Struct(_) = array.var_type {
+            return Err(format!("array of structs {} cannot be captured by the nested function {name}", array.name));
+        }
+        let mut array = array.clone();
+        array.env = Some(array.name.clone());
+        if array.dims.len() == 1 {
+            array.dims = vec![String::new()];
+        } else if array.dims.iter().any(|length| length.parse::<i32>().is_err()) {
+            return Err(format!("array {} has a size only known at runtime, so it cannot be captured by the nested function {name}", array.name));
+        }
+        hidden.push(array);
+    }
+    // calling another nested function passes along what it captured.
+    let prefix = format!("{enclosing}.");
+    for function in function_table.iter().filter(|function| function.ir_name.starts_with(&prefix) && used.contains(&&function.name)) {
+        for array in &function.hidden {
+            if !hidden.iter().any(|h| h.name == array.name) {
+                hidden.push(array.clone());
+            }
+        }
+    }
+
+    let nested = Enclosing { ir_name: enclosing.clone(), captured, hidden };
+    let function_code = parse_function(tokens, index, function_table, definitions, Some(nested))?.unwrap();
+    Ok((code, function_code))
+}
+
 
 
 
@@ -1248,11 +3688,12 @@ TESTS
 mod tests {
     use crate::Token;
     use crate::lex;
+    use crate::expand_macros;
 
     #[test]
     fn lexer_test() {
         // test that lexer works on correct cases
-        let toks = lex("1 + 2 + 3").unwrap();
+        let (toks, _) = lex("1 + 2 + 3").unwrap();
         assert!(toks.len() == 5);
         assert!(matches!(toks[0], Token::Num(1)));
         assert!(matches!(toks[1], Token::Plus));
@@ -1260,7 +3701,7 @@ mod tests {
         assert!(matches!(toks[3], Token::Plus));
         assert!(matches!(toks[4], Token::Num(3)));
 
-        let toks = lex("3 + 215 +-").unwrap();
+        let (toks, _) = lex("3 + 215 +-").unwrap();
         assert!(toks.len() == 5);
         assert!(matches!(toks[0], Token::Num(3)));
         assert!(matches!(toks[1], Token::Plus));
@@ -1268,8 +3709,103 @@ mod tests {
         assert!(matches!(toks[3], Token::Plus));
         assert!(matches!(toks[4], Token::Subtract));
 
+        let (toks, _) = lex("float x = 2.5 * 3").unwrap();
+        assert!(toks.len() == 6);
+        assert!(matches!(toks[0], Token::Float));
+        assert!(matches!(toks[3], Token::FloatNum(_)));
+        assert!(matches!(toks[5], Token::Num(3)));
+
+        let (toks, _) = lex("m[i][j] += 2").unwrap();
+        assert!(toks.len() == 9);
+        assert!(matches!(toks[7], Token::PlusAssign));
+        assert!(matches!(toks[8], Token::Num(2)));
+
+        let (toks, _) = lex("printf(\"%d\\n\", x)").unwrap();
+        assert!(toks.len() == 6);
+        assert!(matches!(&toks[2], Token::Str(text) if text == "%d\n"));
+        assert!(lex("\"unterminated").is_err());
+
+        let (toks, _) = lex("i++ m[i]--").unwrap();
+        assert!(toks.len() == 7);
+        assert!(matches!(toks[1], Token::Increment));
+        assert!(matches!(toks[6], Token::Decrement));
+
+        let (toks, _) = lex("points[1].x = 1.5").unwrap();
+        assert!(toks.len() == 8);
+        assert!(matches!(toks[0], Token::Ident(_)));
+        assert!(matches!(toks[4], Token::Dot));
+        assert!(matches!(toks[7], Token::FloatNum(_)));
+
+        let (toks, _) = lex("enum Color { Red, Green }").unwrap();
+        assert!(toks.len() == 7);
+        assert!(matches!(toks[0], Token::Enum));
+        assert!(matches!(toks[4], Token::Comma));
+
+        let (toks, _) = lex("const int N = 10 * 4;").unwrap();
+        assert!(toks.len() == 8);
+        assert!(matches!(toks[0], Token::Const));
+        assert!(matches!(toks[2], Token::Ident(_)));
+
+        let (toks, _) = lex("outer: while i < n { break outer; }").unwrap();
+        assert!(toks.len() == 11);
+        assert!(matches!(toks[1], Token::Colon));
+        assert!(matches!(toks[7], Token::Break));
+
+        let (toks, _) = lex("do { read(n); } while n < 1;").unwrap();
+        assert!(toks.len() == 13);
+        assert!(matches!(toks[0], Token::Do));
+        assert!(matches!(toks[8], Token::While));
+
+        let (toks, _) = lex("for i in 0..10 { }").unwrap();
+        assert!(toks.len() == 8);
+        assert!(matches!(toks[0], Token::For));
+        assert!(matches!(toks[2], Token::In));
+        assert!(matches!(toks[3], Token::Num(0)));
+        assert!(matches!(toks[4], Token::DotDot));
+
+        let (toks, _) = lex("a > b ? a : b").unwrap();
+        assert!(toks.len() == 7);
+        assert!(matches!(toks[3], Token::Question));
+        assert!(matches!(toks[5], Token::Colon));
+
+        let (toks, lines) = lex("int a;\nassert(a == 0);").unwrap();
+        assert!(matches!(toks[3], Token::Assert(2)));
+        assert!(lines[2] == 1 && lines[3] == 2);
+
         // test that the lexer catches invalid tokens
         assert!(matches!(lex("^^^"), Err(_)));
     }
 
+    #[test]
+    fn macro_test() {
+        let expand = |code: &str| {
+            let (toks, lines) = lex(code).unwrap();
+            expand_macros(toks, lines)
+        };
+
+        // the definition is removed and the argument replaces the parameter, in parentheses.
+        let toks = expand("macro twice(x) { x * 2 }\nn = twice(3 + 1);").unwrap();
+        assert!(toks.len() == 10);
+        assert!(matches!(toks[2], Token::LeftParen));
+        assert!(matches!(toks[3], Token::Num(3)));
+        assert!(matches!(toks[6], Token::RightParen));
+        assert!(matches!(toks[7], Token::Multiply));
+
+        // an array element can still be assigned to.
+        let toks = expand("macro clear(x) { x = 0 }\nclear(a[1]);").unwrap();
+        assert!(matches!(toks[0], Token::Ident(_)) && matches!(toks[4], Token::Assign));
+
+        // variables declared in the body are renamed for every use, the ';' after the use is dropped.
+        let toks = expand("macro zero(v) { int t; t = 0; v = t; }\nzero(t); zero(t);").unwrap();
+        assert!(toks.len() == 22);
+        assert!(matches!(&toks[1], Token::Ident(name) if name == "zero.1.t"));
+        assert!(matches!(&toks[12], Token::Ident(name) if name == "zero.2.t"));
+        assert!(matches!(&toks[7], Token::Ident(name) if name == "t"));
+
+        // errors name the line of the use and of the definition.
+        let error = expand("macro twice(x) { x * 2 }\n\nn = twice(1, 2);").unwrap_err();
+        assert!(error.contains("line 3") && error.contains("line 1"));
+        assert!(expand("macro a(x) { a(x) }\na(1);").is_err());
+    }
+
 }
\ No newline at end of file